@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+
+use crate::syntax::*;
+
+/// The names a function declares and uses, which tell whether it could see
+/// the variables of the functions calling it, as variables are looked up in
+/// the calling functions when the function itself does not declare them.
+#[derive(Default)]
+pub struct FnVars {
+    /// The parameters and local variables the function declares.
+    pub locals: HashSet<String>,
+    /// The variables it uses where none of its own of that name is in scope.
+    pub free: HashSet<String>,
+    /// The functions it calls, along with those it names in strings, which
+    /// builtins such as `map` may call.
+    pub calls: HashSet<String>,
+}
+
+impl FnVars {
+    pub fn new(params: &[String], body: &[Stmt]) -> Self {
+        let mut scanner = Scanner {
+            scopes: vec![params.iter().map(String::as_str).collect()],
+            vars: FnVars::default(),
+        };
+
+        scanner.vars.locals.extend(params.iter().cloned());
+        scanner.scan_body(body);
+
+        scanner.vars
+    }
+}
+
+/// Walks a function body, keeping track of the variables in scope.
+struct Scanner<'a> {
+    scopes: Vec<HashSet<&'a str>>,
+    vars: FnVars,
+}
+
+impl<'a> Scanner<'a> {
+    fn declare(&mut self, var: &'a str) {
+        self.scopes
+            .last_mut()
+            .expect("no scope to declare in")
+            .insert(var);
+        self.vars.locals.insert(var.to_owned());
+    }
+
+    fn use_var(&mut self, var: &str) {
+        if !self.scopes.iter().any(|scope| scope.contains(var)) {
+            self.vars.free.insert(var.to_owned());
+        }
+    }
+
+    fn scan_body(&mut self, body: &'a [Stmt]) {
+        self.scopes.push(HashSet::new());
+
+        for stmt in body {
+            self.scan_stmt(stmt);
+        }
+
+        self.scopes.pop();
+    }
+
+    fn scan_stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::FnCall(fn_call) => self.scan_fn_call(fn_call),
+            Stmt::Return(ReturnStmt { expr })
+            | Stmt::Throw(ThrowStmt { expr, .. }) => self.scan_expr(expr),
+            Stmt::If(IfStmt { cond, body, .. })
+            | Stmt::While(WhileStmt { cond, body, .. }) => {
+                self.scan_expr(cond);
+                self.scan_body(body);
+            }
+            Stmt::Assign(AssignStmt { var, val, .. }) => {
+                self.scan_expr(val);
+                self.use_var(var);
+            }
+            Stmt::Decl(DeclStmt { var, val }) => {
+                self.scan_expr(val);
+                self.declare(var);
+            }
+            // Globals are only declared at the top level of the program.
+            Stmt::Global(GlobalStmt { val, .. }) => self.scan_expr(val),
+            Stmt::Try(TryStmt {
+                body,
+                catch,
+                finally,
+            }) => {
+                self.scan_body(body);
+
+                if let Some(CatchClause { var, body }) = catch {
+                    self.scopes.push(HashSet::new());
+                    self.declare(var);
+                    self.scan_body(body);
+                    self.scopes.pop();
+                }
+
+                if let Some(body) = finally {
+                    self.scan_body(body);
+                }
+            }
+        }
+    }
+
+    fn scan_fn_call(&mut self, fn_call: &'a FnCall) {
+        self.vars.calls.insert(fn_call.name.clone());
+
+        for arg in fn_call.args.iter() {
+            if let Expr::StringLiteral(name) = arg {
+                let name = match &fn_call.fn_names {
+                    Some(fn_names) => fn_names.resolve(name).unwrap_or(name),
+                    None => name,
+                };
+
+                self.vars.calls.insert(name.to_owned());
+            }
+
+            self.scan_expr(arg);
+        }
+    }
+
+    fn scan_expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Identfier(name, _) => self.use_var(name),
+            Expr::FnCall(fn_call) => self.scan_fn_call(fn_call),
+            Expr::FieldAccess(FieldAccess { obj, .. }) => self.scan_expr(obj),
+            Expr::Binary(Binary { left, right, .. }) => {
+                self.scan_expr(left);
+                self.scan_expr(right);
+            }
+            Expr::Unary(Unary { expr, .. }) => self.scan_expr(expr),
+            Expr::ObjectLiteral(fields) => {
+                for (_, expr) in fields.iter() {
+                    self.scan_expr(expr);
+                }
+            }
+            Expr::ListLiteral(values) => {
+                for expr in values.iter() {
+                    self.scan_expr(expr);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,1224 @@
+use std::cell::Cell;
+use std::cell::OnceCell;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+
+use crate::gc::Collector;
+use crate::interpreter::*;
+use crate::lexer::Span;
+use crate::limits::Limits;
+use crate::ordered_map::OrderedMap;
+use crate::syntax::*;
+use crate::value::*;
+
+/// The local variable storage of a single compiled function call.
+pub struct Frame<'a> {
+    /// One slot for every parameter and `let` in the function, resolved when
+    /// the function is compiled.
+    slots: Vec<Value>,
+    /// Where the statement running started, given to the errors raised
+    /// without a span of their own, such as running out of fuel.
+    span: Option<Span>,
+
+    ctx: &'a Context,
+}
+
+enum BodyResult {
+    Return(Value),
+    /// A call to the running function in tail position, holding the arguments
+    /// to rerun it with.
+    TailCall(Vec<Value>),
+    None,
+}
+
+type ExprFn = Box<dyn Fn(&mut Frame<'_>) -> Result<Value, RuntimeError>>;
+type StmtFn = Box<dyn Fn(&mut Frame<'_>) -> Result<BodyResult, RuntimeError>>;
+
+/// A compiled statement, along with where it starts.
+struct CompiledStmt {
+    span: Option<Span>,
+    run: StmtFn,
+}
+
+/// The slot of the global `args` list, which every program has.
+const ARGS_SLOT: usize = 0;
+
+/// State shared by every compiled function while the program runs.
+struct Context {
+    depth: Cell<usize>,
+    /// Where the native stack was when the outermost call was made.
+    stack_base: Cell<usize>,
+    limits: Rc<Limits>,
+    collector: Rc<Collector>,
+    builtins: HashMap<String, FnObj>,
+    /// The compiled functions and the indices of their names, set once the
+    /// whole program has been compiled.
+    fns: OnceCell<(FnTable, HashMap<String, usize>)>,
+    /// The names of the functions being called, innermost last.
+    calls: RefCell<Vec<Rc<str>>>,
+    /// The frames the error being raised came from, recorded when it first
+    /// leaves a function so that a catch clause further up still sees them.
+    error_stack: RefCell<Option<Vec<String>>>,
+    /// Where the error being raised came from in the innermost function it
+    /// has not yet left.
+    error_span: Cell<Option<Span>>,
+    /// The calls the error being raised has left so far, innermost first.
+    trace: RefCell<Vec<TraceFrame>>,
+    /// The global variables, which are `None` until their declarations run.
+    globals: RefCell<Vec<Option<Global>>>,
+    /// The slots of the globals by name, set once the whole program has been
+    /// compiled.
+    global_slots: OnceCell<HashMap<String, usize>>,
+    /// What the functions know of each other's variables, set once the whole
+    /// program has been compiled.
+    caller_vars: OnceCell<CallerVars>,
+}
+
+/// The variables of the compiled functions, used to find the calls made by
+/// builtins that the interpreter would run differently, as it looks
+/// variables up in the calling functions before the globals.
+struct CallerVars {
+    /// The parameters and local variables of each function, by name.
+    locals: HashMap<Rc<str>, HashSet<String>>,
+    /// The variables that each function, or any function it calls, uses
+    /// without declaring them, along with the function using each.
+    free: Vec<Vec<(String, Rc<str>)>>,
+}
+
+/// A global variable that has been declared.
+struct Global {
+    value: Value,
+    constant: bool,
+}
+
+impl Context {
+    /// Returns the names of the deepest functions being called, innermost
+    /// first.
+    fn stack_trace(&self) -> Vec<String> {
+        self.calls
+            .borrow()
+            .iter()
+            .rev()
+            .take(MAX_SHOWN_FRAMES)
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Records where in the running function the error was raised, unless
+    /// an expression inside the one at the given span already did.
+    fn raised_at(&self, err: RuntimeError, span: Span) -> RuntimeError {
+        if self.error_span.get().is_none() {
+            self.error_span.set(Some(span));
+        }
+
+        err
+    }
+
+    /// Turns a caught error into the value its catch clause receives.
+    fn error_value(&self, err: RuntimeError) -> Result<Value, RuntimeError> {
+        let stack = self
+            .error_stack
+            .take()
+            .unwrap_or_else(|| self.stack_trace());
+
+        self.error_span.take();
+        self.trace.take();
+
+        caught_value(&self.limits, &self.collector, err, stack)
+    }
+
+    /// Fails if the function, called back by a builtin, uses a variable that
+    /// one of the functions running declares, as the interpreter would give
+    /// it theirs.
+    fn check_caller_vars(&self, index: usize) -> Result<(), RuntimeError> {
+        let vars = self.caller_vars.get().expect("program not compiled");
+        let calls = self.calls.borrow();
+
+        for (var, user) in &vars.free[index] {
+            let declared = calls.iter().any(|caller| {
+                vars.locals
+                    .get(caller)
+                    .is_some_and(|locals| locals.contains(var))
+            });
+
+            if declared {
+                return Err(RuntimeError::CallerVariable(
+                    var.clone(),
+                    user.to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Context {
+    /// Calls the function with the given name for a builtin, or the host,
+    /// giving any builtin it names the context of that call.
+    fn call(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+        caller: &BuiltinCall<'_>,
+    ) -> Result<Value, RuntimeError> {
+        let (fns, names) = self.fns.get().expect("program not compiled");
+
+        if let Some(&index) = names.get(name) {
+            self.check_caller_vars(index)?;
+
+            return fns[index]
+                .get()
+                .expect("function not compiled")
+                .call(self, args);
+        }
+
+        match self.builtins.get(name) {
+            Some(FnObj::Builtin { param_count, body }) => {
+                if args.len() != *param_count {
+                    return Err(RuntimeError::InvalidArgCount(
+                        *param_count,
+                        args.len(),
+                    ));
+                }
+
+                body(caller, args)
+            }
+            _ => Err(RuntimeError::UndefinedIdentifier(name.to_owned())),
+        }
+    }
+}
+
+/// The context a compiled program calls a builtin in.
+struct BuiltinCall<'a> {
+    ctx: &'a Context,
+    /// The slots of the calling function, and those of its variables that
+    /// are in scope at the call, unless the host made it.
+    caller: Option<(&'a [Value], &'a HashMap<String, usize>)>,
+    span: Option<Span>,
+    /// The functions the code calling the builtin can name.
+    fn_names: Option<&'a FnNames>,
+}
+
+impl CallContext for BuiltinCall<'_> {
+    fn call(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let name = callee_name(self.fn_names, name)?;
+        self.ctx.call(name, args, self)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Value> {
+        if let Some((slots, locals)) = self.caller {
+            if let Some(&slot) = locals.get(name) {
+                return Some(slots[slot].copy_shallow());
+            }
+        }
+
+        let slot = *self.ctx.global_slots.get()?.get(name)?;
+        let globals = self.ctx.globals.borrow();
+
+        globals[slot]
+            .as_ref()
+            .map(|global| global.value.copy_shallow())
+    }
+
+    fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+/// A function body that has been turned into closures.
+struct CompiledFn {
+    name: Rc<str>,
+    param_count: usize,
+    slot_count: usize,
+    body: Vec<CompiledStmt>,
+}
+
+impl CompiledFn {
+    fn call(
+        &self,
+        ctx: &Context,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        // The arguments are checked before the call is made, so that the
+        // error comes from the call site as it does in the interpreter.
+        if args.len() != self.param_count {
+            return Err(RuntimeError::InvalidArgCount(
+                self.param_count,
+                args.len(),
+            ));
+        }
+
+        let max_depth = ctx.limits.max_depth();
+        let depth = ctx.depth.get();
+        let position = stack_position();
+
+        if depth == 0 {
+            ctx.stack_base.set(position);
+        }
+
+        if depth >= max_depth {
+            return Err(RuntimeError::StackOverflow(max_depth));
+        }
+
+        // Calls nest on the native stack, which can run out before the
+        // maximum depth is reached.
+        if let Some(stack_size) = ctx.limits.stack_size() {
+            if ctx.stack_base.get().abs_diff(position) > stack_size {
+                return Err(RuntimeError::NativeStackOverflow(stack_size));
+            }
+        }
+
+        ctx.depth.set(ctx.depth.get() + 1);
+        ctx.calls.borrow_mut().push(Rc::clone(&self.name));
+
+        let res = self.run(ctx, args);
+
+        // The traceback is collected as the error unwinds, so that calls
+        // which succeed never pay for it.
+        if let Err(err) = &res {
+            if err.is_catchable() && ctx.error_stack.borrow().is_none() {
+                ctx.error_stack.replace(Some(ctx.stack_trace()));
+            }
+
+            ctx.trace.borrow_mut().push(TraceFrame {
+                name: self.name.to_string(),
+                span: ctx.error_span.take(),
+            });
+        }
+
+        ctx.calls.borrow_mut().pop();
+        ctx.depth.set(ctx.depth.get() - 1);
+
+        res
+    }
+
+    /// Runs the function, reusing its frame for any tail calls it makes to
+    /// itself.
+    fn run(
+        &self,
+        ctx: &Context,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let mut frame = Frame {
+            slots: vec![],
+            span: None,
+            ctx,
+        };
+
+        let res = self.run_frame(&mut frame, args);
+
+        if res.is_err() && ctx.error_span.get().is_none() {
+            ctx.error_span.set(frame.span);
+        }
+
+        res
+    }
+
+    fn run_frame(
+        &self,
+        frame: &mut Frame<'_>,
+        mut args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        loop {
+            if frame.ctx.limits.take_interrupt() {
+                return Err(RuntimeError::Interrupted);
+            }
+
+            frame.slots = args;
+            frame.slots.resize_with(self.slot_count, || Value::Null);
+
+            match run_body(&self.body, frame)? {
+                BodyResult::Return(val) => return Ok(val),
+                BodyResult::None => return Ok(Value::Null),
+                BodyResult::TailCall(next_args) => args = next_args,
+            }
+        }
+    }
+}
+
+/// Returns roughly where the native stack of the current thread ends.
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// The table of user-defined functions, filled in once every declaration has
+/// been compiled so that calls can refer to functions declared later.
+type FnTable = Rc<[OnceCell<CompiledFn>]>;
+
+/// Runs the statements of a body, each of which takes one step of fuel.
+fn run_body(
+    body: &[CompiledStmt],
+    frame: &mut Frame<'_>,
+) -> Result<BodyResult, RuntimeError> {
+    for stmt in body {
+        if stmt.span.is_some() {
+            frame.span = stmt.span;
+        }
+
+        frame.ctx.limits.consume_fuel()?;
+
+        let res = (stmt.run)(frame)?;
+
+        if !matches!(res, BodyResult::None) {
+            return Ok(res);
+        }
+    }
+
+    Ok(BodyResult::None)
+}
+
+/// A program whose declarations have all been compiled into closures.
+pub struct Program {
+    ctx: Rc<Context>,
+    /// The top-level statements, compiled as a function that takes the
+    /// command-line arguments as `args`.
+    script: CompiledFn,
+}
+
+impl Program {
+    /// Calls the function with the given name.
+    pub fn call(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let caller = BuiltinCall {
+            ctx: &self.ctx,
+            caller: None,
+            span: None,
+            fn_names: None,
+        };
+
+        self.finish(self.ctx.call(name, args, &caller))
+    }
+
+    /// Runs the top-level statements with the list of command-line
+    /// arguments as the global `args`.
+    pub fn run_script(&self, args: Value) -> Result<Value, RuntimeError> {
+        self.ctx.globals.borrow_mut()[ARGS_SLOT] = Some(Global {
+            value: args,
+            constant: false,
+        });
+
+        self.finish(self.script.call(&self.ctx, vec![]))
+    }
+
+    /// Attaches the traceback to an error that left the program, and clears
+    /// what was recorded about it.
+    fn finish(
+        &self,
+        res: Result<Value, RuntimeError>,
+    ) -> Result<Value, RuntimeError> {
+        let res = res.map_err(|err| err.traced(self.ctx.trace.take()));
+
+        self.ctx.error_span.take();
+        self.ctx.error_stack.take();
+
+        res
+    }
+
+    /// Returns whether a user-defined function with the given name exists.
+    pub fn has_fn(&self, name: &str) -> bool {
+        let (_, names) = self.ctx.fns.get().expect("program not compiled");
+        names.contains_key(name)
+    }
+}
+
+/// Turns the syntax tree into nested closures, resolving variables to frame
+/// slots and function names to their definitions ahead of time.
+///
+/// Unlike the tree-walking interpreter, variables are resolved lexically, so a
+/// function can only see its own parameters and locals, and the globals. A
+/// program in which a function uses a variable that the interpreter could
+/// find in a calling function is rejected instead.
+pub struct Compiler {
+    ctx: Rc<Context>,
+    fns: FnTable,
+    names: HashMap<String, usize>,
+    scopes: Vec<HashMap<String, usize>>,
+    slot_count: usize,
+    /// The slots of the global variables the program declares.
+    globals: HashMap<String, usize>,
+    /// The name of the function being compiled.
+    current_fn: String,
+    /// The number of parameters of the function being compiled, which a tail
+    /// call has to pass to reuse its frame.
+    current_param_count: usize,
+    /// The number of try statements enclosing the code being compiled, in
+    /// which calls cannot be made in tail position.
+    try_depth: usize,
+    /// The variables and calls of the function being compiled.
+    uses: FnUses,
+}
+
+/// The names a function declares and uses, which tell whether it could see
+/// the variables of the functions calling it in the interpreter.
+#[derive(Default)]
+struct FnUses {
+    /// The parameters and local variables the function declares.
+    locals: HashSet<String>,
+    /// The variables it uses where no local of that name is in scope, in the
+    /// order they first appear.
+    free: Vec<String>,
+    /// The user-defined functions it calls directly.
+    calls: BTreeSet<usize>,
+}
+
+impl Compiler {
+    pub fn new(
+        builtins: &HashMap<String, FnObj>,
+        limits: Rc<Limits>,
+        collector: Rc<Collector>,
+    ) -> Self {
+        // Builtins are shared with the program, so that they can be looked up
+        // by name when a builtin calls back into it.
+        let builtins = builtins
+            .iter()
+            .filter_map(|(name, func)| match func {
+                FnObj::Builtin { param_count, body } => Some((
+                    name.clone(),
+                    FnObj::Builtin {
+                        param_count: *param_count,
+                        body: Rc::clone(body),
+                    },
+                )),
+                FnObj::Defined { .. } => None,
+            })
+            .collect();
+
+        Self {
+            ctx: Rc::new(Context {
+                depth: Cell::new(0),
+                stack_base: Cell::new(0),
+                limits,
+                collector,
+                builtins,
+                fns: OnceCell::new(),
+                calls: RefCell::new(vec![]),
+                error_stack: RefCell::new(None),
+                error_span: Cell::new(None),
+                trace: RefCell::new(vec![]),
+                globals: RefCell::new(vec![]),
+                global_slots: OnceCell::new(),
+                caller_vars: OnceCell::new(),
+            }),
+            fns: Rc::new([]),
+            names: HashMap::new(),
+            scopes: vec![],
+            slot_count: 0,
+            globals: HashMap::new(),
+            current_fn: String::new(),
+            current_param_count: 0,
+            try_depth: 0,
+            uses: FnUses::default(),
+        }
+    }
+
+    /// Compiles the program, failing if a function in it uses a variable
+    /// that a function calling it directly declares.
+    pub fn compile(mut self, decls: &[Decl]) -> Result<Program, RuntimeError> {
+        let mut fn_decls = vec![];
+        let mut script = vec![];
+
+        self.globals.insert("args".to_owned(), ARGS_SLOT);
+
+        for decl in decls {
+            match decl {
+                Decl::FnDecl(fn_decl) => fn_decls.push(fn_decl),
+                Decl::Stmt(stmt) => {
+                    if let Stmt::Global(GlobalStmt { var, .. }) = stmt {
+                        let slot = self.globals.len();
+                        self.globals.entry(var.clone()).or_insert(slot);
+                    }
+
+                    script.push(stmt);
+                }
+                // Imports are resolved before the program is compiled.
+                Decl::Import(_) => {}
+            }
+        }
+
+        self.ctx
+            .globals
+            .replace((0..self.globals.len()).map(|_| None).collect());
+
+        // Later declarations replace earlier ones with the same name, just as
+        // they do in the interpreter's function map.
+        for (index, fn_decl) in fn_decls.iter().enumerate() {
+            self.names.insert(fn_decl.name.clone(), index);
+        }
+
+        self.fns = (0..fn_decls.len()).map(|_| OnceCell::new()).collect();
+
+        let mut uses = vec![];
+
+        for (index, fn_decl) in fn_decls.into_iter().enumerate() {
+            // Only the declaration that the name refers to can be the target
+            // of a tail call, so shadowed ones are compiled without them.
+            let shadowed = self.names[&fn_decl.name] != index;
+            self.current_fn = match shadowed {
+                false => fn_decl.name.clone(),
+                true => String::new(),
+            };
+            self.current_param_count = fn_decl.params.len();
+
+            let compiled =
+                self.compile_fn(&fn_decl.name, &fn_decl.params, &fn_decl.body);
+            let _ = self.fns[index].set(compiled);
+
+            // Shadowed declarations never run, so their variables cannot
+            // clash with anything.
+            let fn_uses = mem::take(&mut self.uses);
+            uses.push(if shadowed { FnUses::default() } else { fn_uses });
+        }
+
+        // The script is not a function it can call, so it makes no tail
+        // calls.
+        self.current_fn = String::new();
+        let script = self.compile_fn("<script>", &[], script);
+        uses.push(mem::take(&mut self.uses));
+
+        let mut names = self
+            .fns
+            .iter()
+            .map(|func| {
+                Rc::clone(&func.get().expect("function not compiled").name)
+            })
+            .collect::<Vec<_>>();
+        names.push(Rc::clone(&script.name));
+
+        let caller_vars = check_caller_vars(&names, &uses)?;
+        let _ = self.ctx.caller_vars.set(caller_vars);
+        let _ = self.ctx.fns.set((self.fns, self.names));
+        let _ = self.ctx.global_slots.set(self.globals);
+
+        Ok(Program {
+            ctx: self.ctx,
+            script,
+        })
+    }
+
+    fn compile_fn<'a>(
+        &mut self,
+        name: &str,
+        params: &[String],
+        body: impl IntoIterator<Item = &'a Stmt>,
+    ) -> CompiledFn {
+        self.slot_count = 0;
+        self.scopes.push(HashMap::new());
+
+        for param in params.iter() {
+            self.declare(param);
+        }
+
+        let body = body
+            .into_iter()
+            .map(|stmt| self.compile_stmt(stmt))
+            .collect();
+
+        self.scopes.pop();
+
+        CompiledFn {
+            name: name.into(),
+            param_count: params.len(),
+            slot_count: self.slot_count,
+            body,
+        }
+    }
+
+    /// Allocates a new slot for the variable in the innermost scope.
+    fn declare(&mut self, var: &str) -> usize {
+        let slot = self.slot_count;
+        self.slot_count += 1;
+
+        self.scopes
+            .last_mut()
+            .expect("no scope to declare in")
+            .insert(var.to_owned(), slot);
+        self.uses.locals.insert(var.to_owned());
+
+        slot
+    }
+
+    /// Records a use of a variable that is not a local in scope.
+    fn use_free(&mut self, var: &str) {
+        if !self.uses.free.iter().any(|free| free == var) {
+            self.uses.free.push(var.to_owned());
+        }
+    }
+
+    /// Returns the slots of the variables in scope, by name.
+    fn visible_locals(&self) -> HashMap<String, usize> {
+        self.scopes
+            .iter()
+            .flat_map(|scope| {
+                scope.iter().map(|(var, &slot)| (var.clone(), slot))
+            })
+            .collect()
+    }
+
+    fn resolve(&self, var: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(var).copied())
+    }
+
+    fn compile_body(&mut self, body: &[Stmt]) -> Vec<CompiledStmt> {
+        self.scopes.push(HashMap::new());
+
+        let body = body.iter().map(|stmt| self.compile_stmt(stmt)).collect();
+
+        self.scopes.pop();
+
+        body
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) -> CompiledStmt {
+        CompiledStmt {
+            span: stmt.span(),
+            run: self.compile_stmt_fn(stmt),
+        }
+    }
+
+    fn compile_stmt_fn(&mut self, stmt: &Stmt) -> StmtFn {
+        match stmt {
+            Stmt::FnCall(fn_call) => {
+                let call = self.compile_fn_call(fn_call);
+
+                Box::new(move |frame| {
+                    call(frame)?;
+                    Ok(BodyResult::None)
+                })
+            }
+            Stmt::If(IfStmt { cond, body, span }) => {
+                let cond = self.compile_expr(cond);
+                let body = self.compile_body(body);
+                let span = *span;
+
+                Box::new(move |frame| {
+                    let cond_val = condition(cond(frame)?)
+                        .map_err(|err| frame.ctx.raised_at(err, span))?;
+
+                    if cond_val {
+                        run_body(&body, frame)
+                    } else {
+                        Ok(BodyResult::None)
+                    }
+                })
+            }
+            Stmt::While(WhileStmt { cond, body, span }) => {
+                let cond = self.compile_expr(cond);
+                let body = self.compile_body(body);
+                let span = *span;
+
+                Box::new(move |frame| loop {
+                    let value = cond(frame)?;
+
+                    // Each check of the condition takes a step, so that a loop
+                    // with an empty body still uses up fuel.
+                    frame
+                        .ctx
+                        .limits
+                        .consume_fuel()
+                        .map_err(|err| frame.ctx.raised_at(err, span))?;
+
+                    let result = condition(value)
+                        .map_err(|err| frame.ctx.raised_at(err, span))?;
+
+                    if !result {
+                        return Ok(BodyResult::None);
+                    }
+
+                    if frame.ctx.limits.take_interrupt() {
+                        return Err(RuntimeError::Interrupted);
+                    }
+
+                    let result = run_body(&body, frame)?;
+
+                    if !matches!(result, BodyResult::None) {
+                        return Ok(result);
+                    }
+                })
+            }
+            Stmt::Return(ReturnStmt {
+                expr: Expr::FnCall(FnCall { name, args, .. }),
+            }) if *name == self.current_fn
+                && args.len() == self.current_param_count
+                && self.try_depth == 0 =>
+            {
+                self.uses.calls.insert(self.names[name]);
+
+                let args = args
+                    .iter()
+                    .map(|arg| self.compile_expr(arg))
+                    .collect::<Vec<_>>();
+
+                Box::new(move |frame| {
+                    let args = args
+                        .iter()
+                        .map(|arg| arg(frame))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    Ok(BodyResult::TailCall(args))
+                })
+            }
+            Stmt::Return(ReturnStmt { expr }) => {
+                let expr = self.compile_expr(expr);
+
+                Box::new(move |frame| Ok(BodyResult::Return(expr(frame)?)))
+            }
+            Stmt::Throw(ThrowStmt { expr, span }) => {
+                let expr = self.compile_expr(expr);
+                let span = *span;
+
+                Box::new(move |frame| {
+                    let err = RuntimeError::Thrown(expr(frame)?);
+                    Err(frame.ctx.raised_at(err, span))
+                })
+            }
+            Stmt::Try(TryStmt {
+                body,
+                catch,
+                finally,
+            }) => {
+                // A call in the catch clause has to finish before the finally
+                // body runs too.
+                self.try_depth += 1;
+
+                let body = self.compile_body(body);
+
+                let catch = catch.as_ref().map(|CatchClause { var, body }| {
+                    self.scopes.push(HashMap::new());
+                    let slot = self.declare(var);
+                    let body = self.compile_body(body);
+                    self.scopes.pop();
+
+                    (slot, body)
+                });
+
+                self.try_depth -= 1;
+
+                let finally =
+                    finally.as_ref().map(|body| self.compile_body(body));
+
+                Box::new(move |frame| {
+                    let res = match (run_body(&body, frame), &catch) {
+                        (Err(err), Some((slot, catch_body)))
+                            if err.is_catchable() =>
+                        {
+                            frame.slots[*slot] = frame.ctx.error_value(err)?;
+                            run_body(catch_body, frame)
+                        }
+                        (res, _) => res,
+                    };
+
+                    // Errors that cannot be caught skip the finally body too.
+                    let Some(finally) = &finally else {
+                        return res;
+                    };
+
+                    if let Err(err) = &res {
+                        if !err.is_catchable() {
+                            return res;
+                        }
+                    }
+
+                    match run_body(finally, frame)? {
+                        BodyResult::None => res,
+                        result => Ok(result),
+                    }
+                })
+            }
+            Stmt::Assign(AssignStmt { var, val, span }) => {
+                let val = self.compile_expr(val);
+                let span = *span;
+
+                let slot = self.resolve(var);
+
+                if slot.is_none() {
+                    self.use_free(var);
+                }
+
+                match slot {
+                    Some(slot) => Box::new(move |frame| {
+                        frame.slots[slot] = val(frame)?;
+                        Ok(BodyResult::None)
+                    }),
+                    None if self.globals.contains_key(var) => {
+                        let slot = self.globals[var];
+                        let var = var.clone();
+
+                        Box::new(move |frame| {
+                            let value = val(frame)?;
+                            let mut globals = frame.ctx.globals.borrow_mut();
+
+                            let err = match &mut globals[slot] {
+                                Some(global) if !global.constant => {
+                                    global.value = value;
+                                    return Ok(BodyResult::None);
+                                }
+                                Some(_) => RuntimeError::ConstantAssignment(
+                                    var.clone(),
+                                ),
+                                None => RuntimeError::UndefinedIdentifier(
+                                    var.clone(),
+                                ),
+                            };
+
+                            Err(frame.ctx.raised_at(err, span))
+                        })
+                    }
+                    None => {
+                        let var = var.clone();
+
+                        Box::new(move |frame| {
+                            val(frame)?;
+
+                            let err =
+                                RuntimeError::UndefinedIdentifier(var.clone());
+                            Err(frame.ctx.raised_at(err, span))
+                        })
+                    }
+                }
+            }
+            Stmt::Decl(DeclStmt { var, val }) => {
+                // The value is compiled first so that `let x = x;` refers to
+                // the outer variable.
+                let val = self.compile_expr(val);
+                let slot = self.declare(var);
+
+                Box::new(move |frame| {
+                    frame.slots[slot] = val(frame)?;
+                    Ok(BodyResult::None)
+                })
+            }
+            Stmt::Global(GlobalStmt {
+                var, val, constant, ..
+            }) => {
+                let val = self.compile_expr(val);
+                let slot = self.globals[var];
+                let constant = *constant;
+
+                Box::new(move |frame| {
+                    let value = val(frame)?;
+                    frame.ctx.globals.borrow_mut()[slot] =
+                        Some(Global { value, constant });
+
+                    Ok(BodyResult::None)
+                })
+            }
+        }
+    }
+
+    fn compile_fn_call(&mut self, fn_call: &FnCall) -> ExprFn {
+        let FnCall {
+            name,
+            args,
+            span,
+            fn_names,
+        } = fn_call;
+        let span = *span;
+
+        let args = args
+            .iter()
+            .map(|arg| self.compile_expr(arg))
+            .collect::<Vec<_>>();
+
+        let eval_args = move |frame: &mut Frame| {
+            args.iter()
+                .map(|arg| arg(frame))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        if let Some(&index) = self.names.get(name) {
+            self.uses.calls.insert(index);
+
+            // The table is looked up through the context rather than captured,
+            // as the functions in it hold this closure.
+            return Box::new(move |frame| {
+                let args = eval_args(frame)?;
+                let (fns, _) =
+                    frame.ctx.fns.get().expect("program not compiled");
+
+                fns[index]
+                    .get()
+                    .expect("function not compiled")
+                    .call(frame.ctx, args)
+                    .map_err(|err| frame.ctx.raised_at(err, span))
+            });
+        }
+
+        match self.ctx.builtins.get(name) {
+            Some(FnObj::Builtin { param_count, body }) => {
+                let param_count = *param_count;
+                let body = Rc::clone(body);
+                let locals = self.visible_locals();
+                let fn_names = fn_names.clone();
+
+                Box::new(move |frame| {
+                    let args = eval_args(frame)?;
+
+                    if args.len() != param_count {
+                        let err = RuntimeError::InvalidArgCount(
+                            param_count,
+                            args.len(),
+                        );
+                        return Err(frame.ctx.raised_at(err, span));
+                    }
+
+                    let caller = BuiltinCall {
+                        ctx: frame.ctx,
+                        caller: Some((&frame.slots, &locals)),
+                        span: Some(span),
+                        fn_names: fn_names.as_deref(),
+                    };
+
+                    body(&caller, args)
+                        .map_err(|err| frame.ctx.raised_at(err, span))
+                })
+            }
+            _ => {
+                let name = name.clone();
+
+                Box::new(move |frame| {
+                    let err = RuntimeError::UndefinedIdentifier(name.clone());
+                    Err(frame.ctx.raised_at(err, span))
+                })
+            }
+        }
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> ExprFn {
+        match expr {
+            Expr::Identfier(name, span) => match self.resolve(name) {
+                Some(slot) => {
+                    Box::new(move |frame| Ok(frame.slots[slot].copy_shallow()))
+                }
+                None if self.globals.contains_key(name) => {
+                    self.use_free(name);
+
+                    let slot = self.globals[name];
+                    let name = name.clone();
+                    let span = *span;
+
+                    Box::new(move |frame| {
+                        match &frame.ctx.globals.borrow()[slot] {
+                            Some(global) => Ok(global.value.copy_shallow()),
+                            None => {
+                                let err = RuntimeError::UndefinedIdentifier(
+                                    name.clone(),
+                                );
+                                Err(frame.ctx.raised_at(err, span))
+                            }
+                        }
+                    })
+                }
+                None => {
+                    self.use_free(name);
+
+                    let name = name.clone();
+                    let span = *span;
+
+                    Box::new(move |frame| {
+                        let err =
+                            RuntimeError::UndefinedIdentifier(name.clone());
+                        Err(frame.ctx.raised_at(err, span))
+                    })
+                }
+            },
+            Expr::NumberLiteral(num) => {
+                let num = *num;
+                Box::new(move |_| Ok(Value::Number(num)))
+            }
+            Expr::BooleanLiteral(bool) => {
+                let bool = *bool;
+                Box::new(move |_| Ok(Value::Boolean(bool)))
+            }
+            Expr::NullLiteral => Box::new(|_| Ok(Value::Null)),
+            Expr::StringLiteral(str) => {
+                let str = str.clone();
+
+                Box::new(move |frame| {
+                    let value = Value::String(Rc::new(str.clone()));
+                    account(&frame.ctx.limits, &frame.ctx.collector, &value)?;
+
+                    Ok(value)
+                })
+            }
+            Expr::FnCall(fn_call) => self.compile_fn_call(fn_call),
+            Expr::Binary(bin_expr) => {
+                let left = self.compile_expr(&bin_expr.left);
+                let right = self.compile_expr(&bin_expr.right);
+                let op = bin_expr.op;
+                let span = bin_expr.span;
+
+                Box::new(move |frame| {
+                    let left = left(frame)?;
+                    let right = right(frame)?;
+
+                    let value = left.operate(&right, op).map_err(|err| {
+                        let err = RuntimeError::OperationError(err);
+                        frame.ctx.raised_at(err, span)
+                    })?;
+                    account(&frame.ctx.limits, &frame.ctx.collector, &value)
+                        .map_err(|err| frame.ctx.raised_at(err, span))?;
+
+                    Ok(value)
+                })
+            }
+            Expr::Unary(unary_expr) => {
+                let expr = self.compile_expr(&unary_expr.expr);
+                let op = unary_expr.op;
+                let span = unary_expr.span;
+
+                Box::new(move |frame| {
+                    expr(frame)?.operate_unary(op).map_err(|err| {
+                        let err = RuntimeError::OperationError(err);
+                        frame.ctx.raised_at(err, span)
+                    })
+                })
+            }
+            Expr::ObjectLiteral(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, expr)| (name.clone(), self.compile_expr(expr)))
+                    .collect::<Vec<_>>();
+
+                Box::new(move |frame| {
+                    let mut object = OrderedMap::new();
+
+                    for (name, expr) in fields.iter() {
+                        object.insert(name.clone(), expr(frame)?);
+                    }
+
+                    let value = Value::Object(Rc::new(RefCell::new(object)));
+                    account(&frame.ctx.limits, &frame.ctx.collector, &value)?;
+
+                    Ok(value)
+                })
+            }
+            Expr::ListLiteral(values) => {
+                let values = values
+                    .iter()
+                    .map(|expr| self.compile_expr(expr))
+                    .collect::<Vec<_>>();
+
+                Box::new(move |frame| {
+                    let list = values
+                        .iter()
+                        .map(|expr| expr(frame))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    let value = Value::List(Rc::new(RefCell::new(list)));
+                    account(&frame.ctx.limits, &frame.ctx.collector, &value)?;
+
+                    Ok(value)
+                })
+            }
+            Expr::FieldAccess(FieldAccess { obj, field, span }) => {
+                let obj = self.compile_expr(obj);
+                let field = field.clone();
+                let span = *span;
+
+                Box::new(move |frame| {
+                    obj(frame)?.field(&field).map_err(|err| {
+                        let err = RuntimeError::OperationError(err);
+                        frame.ctx.raised_at(err, span)
+                    })
+                })
+            }
+        }
+    }
+}
+
+/// Fails if a function uses a variable that one of the functions which can
+/// call it directly declares, as the interpreter would give it theirs, and
+/// otherwise returns what is needed to check the calls made by builtins as
+/// the program runs.
+///
+/// The uses and names are given for each function, followed by the script.
+fn check_caller_vars(
+    names: &[Rc<str>],
+    uses: &[FnUses],
+) -> Result<CallerVars, RuntimeError> {
+    let calls = uses
+        .iter()
+        .map(|fn_uses| fn_uses.calls.iter().copied().collect())
+        .collect::<Vec<Vec<_>>>();
+
+    let mut callers = vec![vec![]; uses.len()];
+
+    for (caller, callees) in calls.iter().enumerate() {
+        for &callee in callees {
+            callers[callee].push(caller);
+        }
+    }
+
+    for (index, fn_uses) in uses.iter().enumerate() {
+        for caller in reachable(index, &callers) {
+            let locals = &uses[caller].locals;
+
+            if let Some(var) =
+                fn_uses.free.iter().find(|&var| locals.contains(var))
+            {
+                return Err(RuntimeError::CallerVariable(
+                    var.clone(),
+                    names[index].to_string(),
+                ));
+            }
+        }
+    }
+
+    // A shadowed declaration comes before the one replacing it, whose locals
+    // are the ones kept.
+    let locals = names
+        .iter()
+        .zip(uses)
+        .map(|(name, fn_uses)| (Rc::clone(name), fn_uses.locals.clone()))
+        .collect();
+
+    let free = (0..uses.len())
+        .map(|index| {
+            let mut users = reachable(index, &calls);
+            users.insert(index);
+
+            users
+                .into_iter()
+                .flat_map(|user| {
+                    uses[user]
+                        .free
+                        .iter()
+                        .map(move |var| (var.clone(), Rc::clone(&names[user])))
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(CallerVars { locals, free })
+}
+
+/// Returns the nodes that can be reached from the start by following one or
+/// more edges.
+fn reachable(start: usize, edges: &[Vec<usize>]) -> BTreeSet<usize> {
+    let mut reached = BTreeSet::new();
+    let mut pending = edges[start].clone();
+
+    while let Some(node) = pending.pop() {
+        if reached.insert(node) {
+            pending.extend(&edges[node]);
+        }
+    }
+
+    reached
+}
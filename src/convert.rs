@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interpreter::RuntimeError;
+use crate::ordered_map::OrderedMap;
+use crate::value::Value;
+
+/// A Rust type that can be taken from a script value, such as an argument
+/// passed to a host function.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, RuntimeError>;
+}
+
+/// A Rust type that can be turned into a script value, such as the result of
+/// a host function.
+pub trait IntoValue {
+    fn into_value(self) -> Result<Value, RuntimeError>;
+}
+
+fn type_error(expected: &str, value: &Value) -> RuntimeError {
+    RuntimeError::InvalidArgumentType(expected.into(), value.type_name().into())
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(self)
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Number(num) => Ok(num),
+            _ => Err(type_error("number", &value)),
+        }
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(self))
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        i32::from_value(value).map(i64::from)
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        i32::try_from(self)
+            .map(Value::Number)
+            .map_err(|_| RuntimeError::NumberOutOfRange(self))
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Boolean(bool) => Ok(bool),
+            _ => Err(type_error("boolean", &value)),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(Value::Boolean(self))
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::String(str) => Ok(Rc::unwrap_or_clone(str)),
+            _ => Err(type_error("string", &value)),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(Value::String(Rc::new(self)))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(Value::String(Rc::new(self.to_owned())))
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(Value::Null)
+    }
+}
+
+/// `null` is taken as `None`, and any other value as `Some`.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Null => Ok(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        match self {
+            Some(value) => value.into_value(),
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+/// Copies the items out of a list. Use `Rc<RefCell<Vec<Value>>>` instead to
+/// modify the list itself.
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        let Value::List(list) = value else {
+            return Err(type_error("list", &value));
+        };
+
+        let list = list.borrow();
+
+        list.iter()
+            .map(|item| T::from_value(item.copy_shallow()))
+            .collect()
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        let list = self
+            .into_iter()
+            .map(IntoValue::into_value)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Value::List(Rc::new(RefCell::new(list))))
+    }
+}
+
+impl FromValue for Rc<RefCell<Vec<Value>>> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::List(list) => Ok(list),
+            _ => Err(type_error("list", &value)),
+        }
+    }
+}
+
+impl IntoValue for Rc<RefCell<Vec<Value>>> {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(Value::List(self))
+    }
+}
+
+impl FromValue for Rc<RefCell<OrderedMap<String, Value>>> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Object(obj) => Ok(obj),
+            _ => Err(type_error("object", &value)),
+        }
+    }
+}
+
+impl IntoValue for Rc<RefCell<OrderedMap<String, Value>>> {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        Ok(Value::Object(self))
+    }
+}
+
+/// A Rust function that can be called from scripts, taking arguments that
+/// implement [`FromValue`] and returning a result that implements
+/// [`IntoValue`]. `Args` is the tuple of argument types, and only tells the
+/// implementations for different numbers of arguments apart.
+pub trait HostFn<Args>: 'static {
+    fn param_count(&self) -> usize;
+
+    /// Converts the arguments, which must be as many as the parameters, and
+    /// calls the function with them.
+    fn call(&self, args: Vec<Value>) -> Result<Value, RuntimeError>;
+}
+
+macro_rules! impl_host_fn {
+    ($($arg:ident),*) => {
+        impl<F, R, E, $($arg),*> HostFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Result<R, E> + 'static,
+            R: IntoValue,
+            E: Into<RuntimeError>,
+            $($arg: FromValue,)*
+        {
+            fn param_count(&self) -> usize {
+                0 $(+ { stringify!($arg); 1 })*
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, args: Vec<Value>) -> Result<Value, RuntimeError> {
+                let mut args = args.into_iter();
+
+                $(
+                    let $arg = $arg::from_value(
+                        args.next().expect("not enough arguments"),
+                    )?;
+                )*
+
+                self($($arg),*).map_err(Into::into)?.into_value()
+            }
+        }
+    };
+}
+
+impl_host_fn!();
+impl_host_fn!(A);
+impl_host_fn!(A, B);
+impl_host_fn!(A, B, C);
+impl_host_fn!(A, B, C, D);
+impl_host_fn!(A, B, C, D, G);
+impl_host_fn!(A, B, C, D, G, H);
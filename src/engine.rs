@@ -0,0 +1,294 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use crate::convert::*;
+use crate::gc::GcStats;
+use crate::interpreter::CallContext;
+use crate::interpreter::Interpreter;
+use crate::interpreter::RuntimeError;
+use crate::lexer::Lexer;
+use crate::limits::InterruptHandle;
+use crate::modules::ModuleError;
+use crate::modules::ModuleLoader;
+use crate::parser::ParseError;
+use crate::parser::Parser;
+use crate::prelude;
+use crate::syntax::Stmt;
+use crate::value::Value;
+
+/// An error raised while loading or running a script.
+#[derive(Debug)]
+pub enum EngineError {
+    Parse(ParseError),
+    Module(ModuleError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(parse_err) => write!(f, "Parse error: {}", parse_err),
+            Self::Module(module_err) => {
+                write!(f, "Module error: {}", module_err)
+            }
+            Self::Runtime(run_err) => write!(f, "Runtime error: {}", run_err),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(parse_err) => Some(parse_err),
+            Self::Module(module_err) => Some(module_err),
+            Self::Runtime(run_err) => Some(run_err),
+        }
+    }
+}
+
+impl From<ParseError> for EngineError {
+    fn from(parse_err: ParseError) -> Self {
+        Self::Parse(parse_err)
+    }
+}
+
+impl From<ModuleError> for EngineError {
+    fn from(module_err: ModuleError) -> Self {
+        Self::Module(module_err)
+    }
+}
+
+impl From<RuntimeError> for EngineError {
+    fn from(run_err: RuntimeError) -> Self {
+        Self::Runtime(run_err)
+    }
+}
+
+/// Hosts scripts inside a Rust program.
+///
+/// Source code is loaded into the engine, after which the functions it
+/// defines can be called with values from the host, and globals can be read
+/// and set between calls. Loading more source adds to the functions already
+/// defined, and its top-level statements are kept until the program is run.
+///
+/// The functions of the standard library, such as `sum` and `range`, are
+/// loaded before any source unless the engine is created with
+/// [`Engine::without_prelude`].
+///
+/// ```
+/// use rlang::{Engine, Value};
+///
+/// let mut engine = Engine::new();
+/// engine.load("fn add(a, b) { return a + b + offset; }").unwrap();
+/// engine.set_global("offset", Value::Number(10));
+///
+/// let sum = engine.call("add", vec![Value::Number(1), Value::Number(2)]);
+/// assert!(matches!(sum.unwrap(), Value::Number(13)));
+/// ```
+pub struct Engine {
+    interpreter: Interpreter,
+    /// Loads the modules that the source imports, relative to the current
+    /// directory.
+    modules: ModuleLoader,
+    /// The top-level statements of everything loaded, in order.
+    script: Vec<Stmt>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        let mut engine = Self::without_prelude();
+        engine.interpreter.load(prelude::decls());
+        engine
+    }
+
+    /// Creates an engine without the standard library, so that only the
+    /// builtins and the functions of the loaded source are defined.
+    pub fn without_prelude() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+            modules: ModuleLoader::new(),
+            script: vec![],
+        }
+    }
+
+    /// Parses the source code and defines the functions it declares.
+    pub fn load(&mut self, source: &str) -> Result<(), EngineError> {
+        let tokens = Lexer::new(source.to_owned()).scan_tokens()?;
+        let decls = Parser::new(tokens).parse()?;
+        let decls = self.modules.load(Path::new("<input>"), decls)?;
+
+        let script = self.interpreter.load(decls);
+        self.script.extend(script);
+
+        Ok(())
+    }
+
+    /// Runs the program: its top-level statements run in order with the
+    /// arguments in the global `args` list, and then its `main` function is
+    /// called with them if it has one.
+    pub fn run(&mut self, args: Vec<String>) -> Result<(), EngineError> {
+        Ok(self.interpreter.run_script(&self.script, args)?)
+    }
+
+    /// Calls the function with the given name, returning its result.
+    pub fn call(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, EngineError> {
+        Ok(self.interpreter.call(name, args)?)
+    }
+
+    /// Returns whether a function with the given name is defined.
+    pub fn has_fn(&self, name: &str) -> bool {
+        self.interpreter.has_fn(name)
+    }
+
+    /// Defines a function that scripts can call, implemented in Rust. Its
+    /// arguments and result are converted with [`FromValue`] and
+    /// [`IntoValue`], and errors it returns stop the script.
+    ///
+    /// ```
+    /// use rlang::Engine;
+    ///
+    /// let mut engine = Engine::new();
+    /// engine.register_fn("repeat", |num: i64, times: i64| {
+    ///     if times < 0 {
+    ///         return Err("cannot repeat a negative number of times");
+    ///     }
+    ///
+    ///     Ok(vec![num; times as usize])
+    /// });
+    /// ```
+    pub fn register_fn<Args>(&mut self, name: &str, func: impl HostFn<Args>) {
+        self.interpreter.register_fn(name, func);
+    }
+
+    /// Defines a function that scripts can call, implemented in Rust, which
+    /// is given its arguments as they are and a context to call functions of
+    /// the program with.
+    pub fn define_fn(
+        &mut self,
+        name: &str,
+        param_count: usize,
+        body: impl Fn(&dyn CallContext, Vec<Value>) -> Result<Value, RuntimeError>
+            + 'static,
+    ) {
+        self.interpreter.define_fn(name, param_count, body);
+    }
+
+    /// Sends what scripts print to the given stream instead of the standard
+    /// output. A [`SharedBuffer`](crate::SharedBuffer) captures it in memory.
+    ///
+    /// ```
+    /// use rlang::{Engine, SharedBuffer};
+    ///
+    /// let output = SharedBuffer::new();
+    ///
+    /// let mut engine = Engine::new();
+    /// engine.set_stdout(output.clone());
+    /// engine.load("fn main() { print(\"Hi\"); }").unwrap();
+    /// engine.run(vec![]).unwrap();
+    ///
+    /// assert_eq!(output.contents(), "Hi\n");
+    /// ```
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.interpreter.set_stdout(stdout);
+    }
+
+    /// Sends the errors scripts print to the given stream instead of the
+    /// standard error.
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.interpreter.set_stderr(stderr);
+    }
+
+    /// Reads the input of scripts from the given stream instead of the
+    /// standard input, such as a `Cursor` over a string.
+    pub fn set_stdin(&mut self, stdin: impl Read + 'static) {
+        self.interpreter.set_stdin(stdin);
+    }
+
+    /// Returns the value of the global variable with the given name.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.get_global(name)
+    }
+
+    /// Declares a global variable, or replaces its value if it already
+    /// exists. Globals are visible to every function.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.interpreter.set_global(name, value);
+    }
+
+    /// Sets the maximum depth of nested function calls.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.interpreter.set_max_depth(max_depth);
+    }
+
+    /// Limits the number of steps scripts may take from now on.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.interpreter.set_fuel(fuel);
+    }
+
+    /// Returns the number of steps left, or `None` if they are not limited.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.interpreter.remaining_fuel()
+    }
+
+    /// Returns a handle that stops running scripts from another thread with a
+    /// [`RuntimeError::Interrupted`].
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// use rlang::{Engine, EngineError, RuntimeError};
+    ///
+    /// let mut engine = Engine::new();
+    /// engine.load("fn main() { while true { } }").unwrap();
+    ///
+    /// let handle = engine.interrupt_handle();
+    ///
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_millis(10));
+    ///     handle.interrupt();
+    /// });
+    ///
+    /// let Err(EngineError::Runtime(err)) = engine.run(vec![]) else {
+    ///     panic!("the script was not interrupted");
+    /// };
+    /// assert!(matches!(err.root(), RuntimeError::Interrupted));
+    /// ```
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interpreter.interrupt_handle()
+    }
+
+    /// Limits the number of bytes scripts may hold at once.
+    pub fn set_max_memory(&mut self, max_memory: usize) {
+        self.interpreter.set_max_memory(max_memory);
+    }
+
+    /// Returns the number of bytes scripts hold.
+    pub fn memory_in_use(&self) -> usize {
+        self.interpreter.memory_in_use()
+    }
+
+    /// Frees lists and objects only kept alive by reference cycles, returning
+    /// how many were freed.
+    pub fn collect_garbage(&self) -> usize {
+        self.interpreter.collect_garbage()
+    }
+
+    /// Returns statistics about the cycle collector.
+    pub fn gc_stats(&self) -> GcStats {
+        self.interpreter.gc_stats()
+    }
+}
@@ -0,0 +1,162 @@
+use crate::interpreter::FnObj;
+use crate::interpreter::RuntimeError;
+use crate::lexer::Span;
+use crate::syntax::*;
+use crate::value::*;
+
+/// A unit of pending work in a call frame. Instead of recursing into the
+/// syntax tree, the interpreter pushes the steps it still has to take onto
+/// the frame and runs them one at a time.
+pub enum Task<'a> {
+    /// Runs a statement.
+    Exec(&'a Stmt),
+    /// Evaluates an expression, pushing its value.
+    Eval(&'a Expr),
+    /// Pops the scope of a body once its statements have run.
+    PopScope,
+    /// Pops a condition and runs the body if it is true.
+    If(&'a IfStmt),
+    /// Pops a condition and runs the body, then the loop again, if it is true.
+    While(&'a WhileStmt),
+    /// Pops the return value and leaves the function.
+    Return,
+    /// Pops the arguments and reruns the current function with them.
+    TailCall(usize, Span),
+    /// Pops a value and assigns it to the variable.
+    Assign(&'a str, Span),
+    /// Pops a value and declares the variable with it.
+    Declare(&'a str),
+    /// Pops a value and declares the global variable with it.
+    DeclareGlobal(&'a GlobalStmt),
+    /// Pops the arguments of the call and calls the function.
+    Call(&'a FnObj, &'a FnCall),
+    /// Pops and drops the value of an expression statement.
+    Discard,
+    /// Pops two operands and applies the operator to them.
+    Binary(Operator, Span),
+    /// Pops an operand and applies the operator to it.
+    Unary(Operator, Span),
+    /// Pops an object and pushes the value of its field with the given name.
+    Field(&'a str, Span),
+    /// Pops the field values and builds an object from them.
+    Object(&'a [(String, Expr)]),
+    /// Pops the given number of values and builds a list from them.
+    List(usize),
+    /// Sits below the body of a `try` statement, or its catch clause, to
+    /// catch errors raised above it. Runs the `finally` body once it is
+    /// reached.
+    Handler(Handler<'a>),
+    /// Pops a value and throws it.
+    Throw(Span),
+    /// Raises the error again, once the `finally` body above it has run,
+    /// from where it was first raised in the frame.
+    Rethrow(RuntimeError, Option<Span>),
+}
+
+impl Task<'_> {
+    /// Returns where in the source the task may raise an error.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Eval(expr) => expr.span(),
+            Self::TailCall(_, span)
+            | Self::Assign(_, span)
+            | Self::Call(_, FnCall { span, .. })
+            | Self::Binary(_, span)
+            | Self::Unary(_, span)
+            | Self::Field(_, span)
+            | Self::Throw(span) => Some(*span),
+            Self::If(IfStmt { span, .. })
+            | Self::While(WhileStmt { span, .. }) => Some(*span),
+            Self::Rethrow(_, span) => *span,
+            _ => None,
+        }
+    }
+}
+
+/// The clauses of a `try` statement that are still to run, and the state of
+/// the frame when it started.
+pub struct Handler<'a> {
+    pub catch: Option<&'a CatchClause>,
+    pub finally: Option<&'a [Stmt]>,
+    /// The number of values the frame held.
+    pub values: usize,
+    /// The number of scopes the frame had pushed.
+    pub scopes: usize,
+}
+
+/// The state of a single call to a defined function, kept on the heap so
+/// that the depth of recursion in a script does not depend on the size of the
+/// native stack.
+pub struct CallFrame<'a> {
+    /// The name of the called function.
+    pub name: &'a str,
+    /// The parameters of the called function.
+    pub params: &'a [String],
+    /// The body of the called function.
+    pub body: &'a [Stmt],
+    /// The work left to do in the function, with the next task last.
+    pub tasks: Vec<Task<'a>>,
+    /// The values produced by evaluated expressions that are yet to be used.
+    pub values: Vec<Value>,
+    /// The number of scopes this call has pushed that are still active.
+    pub scopes: usize,
+    /// Where the last task that could raise an error was, which is the call
+    /// site while a call made by the frame is in progress.
+    pub span: Option<Span>,
+}
+
+impl<'a> CallFrame<'a> {
+    pub fn new(name: &'a str, params: &'a [String], body: &'a [Stmt]) -> Self {
+        Self {
+            name,
+            params,
+            body,
+            tasks: vec![],
+            values: vec![],
+            scopes: 0,
+            span: None,
+        }
+    }
+
+    /// Pops the value produced by the last evaluated expression.
+    pub fn pop_value(&mut self) -> Value {
+        self.values.pop().expect("no value on the frame")
+    }
+
+    /// Returns whether the frame is running a `try` statement.
+    pub fn in_handler(&self) -> bool {
+        self.tasks
+            .iter()
+            .any(|task| matches!(task, Task::Handler(_)))
+    }
+
+    /// Removes the innermost handler, or the innermost one with a `finally`
+    /// body, along with every task above it.
+    pub fn take_handler(&mut self, with_finally: bool) -> Option<Handler<'a>> {
+        let index = self.tasks.iter().rposition(|task| match task {
+            Task::Handler(handler) => {
+                !with_finally || handler.finally.is_some()
+            }
+            _ => false,
+        })?;
+
+        self.tasks.truncate(index + 1);
+
+        match self.tasks.pop() {
+            Some(Task::Handler(handler)) => Some(handler),
+            _ => unreachable!("not a handler"),
+        }
+    }
+
+    /// Pops the values of the last `count` evaluated expressions, in the
+    /// order they were evaluated.
+    pub fn pop_values(&mut self, count: usize) -> Vec<Value> {
+        let start = self
+            .values
+            .len()
+            .checked_sub(count)
+            .expect("not enough values on the frame");
+
+        self.values.split_off(start)
+    }
+}
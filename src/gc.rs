@@ -0,0 +1,400 @@
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use crate::ordered_map::OrderedMap;
+use crate::value::Value;
+
+/// The number of lists and objects that must be created before the first
+/// automatic collection.
+const MIN_THRESHOLD: usize = 10_000;
+
+/// A list or object tracked by the collector.
+enum Container {
+    List(Weak<RefCell<Vec<Value>>>),
+    Object(Weak<RefCell<OrderedMap<String, Value>>>),
+}
+
+impl Container {
+    fn upgrade(&self) -> Option<Value> {
+        match self {
+            Self::List(list) => list.upgrade().map(Value::List),
+            Self::Object(obj) => obj.upgrade().map(Value::Object),
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::List(list) => list.strong_count() > 0,
+            Self::Object(obj) => obj.strong_count() > 0,
+        }
+    }
+
+    /// Returns the number of bytes the container holds, if it is still alive
+    /// and not being changed.
+    fn heap_size(&self) -> usize {
+        match self {
+            Self::List(list) => list
+                .upgrade()
+                .and_then(|list| {
+                    let len = list.try_borrow().ok()?.len();
+                    Some(len * size_of::<Value>())
+                })
+                .unwrap_or(0),
+            Self::Object(obj) => obj
+                .upgrade()
+                .and_then(|obj| {
+                    let size = obj
+                        .try_borrow()
+                        .ok()?
+                        .keys()
+                        .map(|key| key.len() + size_of::<Value>())
+                        .sum();
+                    Some(size)
+                })
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Statistics about the work done by the collector.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct GcStats {
+    /// The number of collections that have run.
+    pub collections: usize,
+    /// The number of lists and objects freed by collections.
+    pub collected: usize,
+    /// The number of lists and objects currently tracked.
+    pub tracked: usize,
+}
+
+/// Frees lists and objects that are only kept alive by reference cycles,
+/// such as a list appended to itself, and measures the memory in use.
+///
+/// Every string, list and object the program creates is registered with the
+/// collector. A collection subtracts the references that tracked containers
+/// hold to each other from their reference counts. Any container left with a
+/// positive count is referenced from outside, by a variable or a value in
+/// use, and everything reachable from those is kept. The rest can only be
+/// reached through cycles, so their contents are cleared to break them.
+pub struct Collector {
+    containers: RefCell<Vec<Container>>,
+    /// The strings registered, which are only looked at to measure memory.
+    strings: RefCell<Vec<Weak<String>>>,
+    /// The number of strings registered that triggers the next sweep of
+    /// those that have been freed.
+    string_threshold: Cell<usize>,
+    /// The number of containers registered since the last collection.
+    registered: Cell<usize>,
+    /// The number of registrations that trigger the next collection.
+    threshold: Cell<usize>,
+    stats: Cell<GcStats>,
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self {
+            containers: RefCell::new(vec![]),
+            strings: RefCell::new(vec![]),
+            string_threshold: Cell::new(MIN_THRESHOLD),
+            registered: Cell::new(0),
+            threshold: Cell::new(MIN_THRESHOLD),
+            stats: Cell::new(GcStats::default()),
+        }
+    }
+}
+
+impl Collector {
+    /// Registers the value if it is a string, list or object, collecting
+    /// garbage if enough lists and objects have been registered since the
+    /// last collection.
+    pub fn track(&self, value: &Value) {
+        let container = match value {
+            Value::List(list) => Container::List(Rc::downgrade(list)),
+            Value::Object(obj) => Container::Object(Rc::downgrade(obj)),
+            Value::String(str) => return self.track_string(str),
+            _ => return,
+        };
+
+        self.containers.borrow_mut().push(container);
+        self.registered.set(self.registered.get() + 1);
+
+        if self.registered.get() >= self.threshold.get() {
+            self.collect();
+        }
+    }
+
+    fn track_string(&self, str: &Rc<String>) {
+        let mut strings = self.strings.borrow_mut();
+        strings.push(Rc::downgrade(str));
+
+        if strings.len() >= self.string_threshold.get() {
+            strings.retain(|str| str.strong_count() > 0);
+            self.string_threshold
+                .set((strings.len() * 2).max(MIN_THRESHOLD));
+        }
+    }
+
+    /// Returns the number of bytes held by the strings, lists and objects
+    /// registered that are still alive, not counting the values themselves.
+    pub fn memory_in_use(&self) -> usize {
+        self.containers
+            .borrow_mut()
+            .retain(|container| container.is_alive());
+        self.strings
+            .borrow_mut()
+            .retain(|str| str.strong_count() > 0);
+
+        let containers = self
+            .containers
+            .borrow()
+            .iter()
+            .map(Container::heap_size)
+            .sum::<usize>();
+        let strings = self
+            .strings
+            .borrow()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|str| str.len())
+            .sum::<usize>();
+
+        containers + strings
+    }
+
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            tracked: self.containers.borrow().len(),
+            ..self.stats.get()
+        }
+    }
+
+    /// Frees every tracked list and object that is unreachable, returning how
+    /// many were freed.
+    pub fn collect(&self) -> usize {
+        self.containers
+            .borrow_mut()
+            .retain(|container| container.is_alive());
+
+        let live = self
+            .containers
+            .borrow()
+            .iter()
+            .filter_map(Container::upgrade)
+            .collect::<Vec<_>>();
+
+        let indices = live
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (address(value), index))
+            .collect::<HashMap<_, _>>();
+
+        // Upgrading each container added a reference of our own, which is
+        // left out of its count.
+        let mut counts = live
+            .iter()
+            .map(|value| strong_count(value) - 1)
+            .collect::<Vec<_>>();
+
+        for (index, value) in live.iter().enumerate() {
+            let complete = for_each_child(value, |child| {
+                if let Some(&child_index) = indices.get(&address(child)) {
+                    counts[child_index] -= 1;
+                }
+            });
+
+            // A container in use elsewhere is treated as referenced from
+            // outside, so that nothing it holds is freed.
+            if !complete {
+                counts[index] = usize::MAX;
+            }
+        }
+
+        let mut reachable = vec![false; live.len()];
+        let mut pending = (0..live.len())
+            .filter(|&index| counts[index] > 0)
+            .collect::<Vec<_>>();
+
+        while let Some(index) = pending.pop() {
+            if reachable[index] {
+                continue;
+            }
+
+            reachable[index] = true;
+
+            for_each_child(&live[index], |child| {
+                if let Some(&child_index) = indices.get(&address(child)) {
+                    if !reachable[child_index] {
+                        pending.push(child_index);
+                    }
+                }
+            });
+        }
+
+        let mut collected = 0;
+
+        for (value, _) in live.iter().zip(reachable).filter(|(_, r)| !r) {
+            clear(value);
+            collected += 1;
+        }
+
+        drop(live);
+
+        self.containers
+            .borrow_mut()
+            .retain(|container| container.is_alive());
+
+        let tracked = self.containers.borrow().len();
+
+        self.registered.set(0);
+        self.threshold.set(tracked.max(MIN_THRESHOLD));
+
+        let mut stats = self.stats.get();
+        stats.collections += 1;
+        stats.collected += collected;
+        self.stats.set(stats);
+
+        collected
+    }
+}
+
+fn address(value: &Value) -> usize {
+    match value {
+        Value::List(list) => Rc::as_ptr(list) as *const () as usize,
+        Value::Object(obj) => Rc::as_ptr(obj) as *const () as usize,
+        _ => unreachable!("only lists and objects are tracked"),
+    }
+}
+
+fn strong_count(value: &Value) -> usize {
+    match value {
+        Value::List(list) => Rc::strong_count(list),
+        Value::Object(obj) => Rc::strong_count(obj),
+        _ => unreachable!("only lists and objects are tracked"),
+    }
+}
+
+/// Calls the function with every list and object directly held by the
+/// container. Returns `false` if the container is in use and could not be
+/// looked into.
+fn for_each_child(value: &Value, f: impl FnMut(&Value)) -> bool {
+    let is_container =
+        |child: &&Value| matches!(child, Value::List(_) | Value::Object(_));
+
+    match value {
+        Value::List(list) => match list.try_borrow() {
+            Ok(list) => list.iter().filter(is_container).for_each(f),
+            Err(_) => return false,
+        },
+        Value::Object(obj) => match obj.try_borrow() {
+            Ok(obj) => obj.values().filter(is_container).for_each(f),
+            Err(_) => return false,
+        },
+        _ => {}
+    }
+
+    true
+}
+
+/// Empties the container, dropping the references it holds once it is no
+/// longer borrowed.
+fn clear(value: &Value) {
+    match value {
+        Value::List(list) => {
+            let items = std::mem::take(&mut *list.borrow_mut());
+            drop(items);
+        }
+        Value::Object(obj) => {
+            let fields = std::mem::take(&mut *obj.borrow_mut());
+            drop(fields);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_list(collector: &Collector, items: Vec<Value>) -> Value {
+        let list = Value::List(Rc::new(RefCell::new(items)));
+        collector.track(&list);
+        list
+    }
+
+    #[test]
+    fn frees_unreachable_cycles() {
+        let collector = Collector::default();
+        let list = new_list(&collector, vec![]);
+
+        if let Value::List(items) = &list {
+            items.borrow_mut().push(list.copy_shallow());
+        }
+
+        drop(list);
+
+        assert_eq!(collector.collect(), 1);
+        assert_eq!(collector.stats().tracked, 0);
+        assert_eq!(collector.stats().collected, 1);
+    }
+
+    #[test]
+    fn keeps_values_still_in_use() {
+        let collector = Collector::default();
+        let inner = new_list(&collector, vec![Value::Number(1)]);
+        let outer = new_list(&collector, vec![inner.copy_shallow()]);
+
+        // The outer list refers to itself, but is still held here.
+        if let Value::List(items) = &outer {
+            items.borrow_mut().push(outer.copy_shallow());
+        }
+
+        drop(inner);
+
+        assert_eq!(collector.collect(), 0);
+        assert_eq!(collector.stats().tracked, 2);
+        assert_eq!(outer.to_string(), "[ [ 1 ], <cycle> ]");
+    }
+
+    #[test]
+    fn frees_everything_a_cycle_holds() {
+        let collector = Collector::default();
+        let inner = new_list(&collector, vec![]);
+        let obj = Value::Object(Rc::new(RefCell::new(
+            [("list".to_owned(), inner.copy_shallow())]
+                .into_iter()
+                .collect(),
+        )));
+        collector.track(&obj);
+
+        if let Value::List(items) = &inner {
+            items.borrow_mut().push(obj.copy_shallow());
+        }
+
+        drop(obj);
+        drop(inner);
+
+        assert_eq!(collector.collect(), 2);
+        assert_eq!(collector.stats().collections, 1);
+    }
+
+    #[test]
+    fn measures_what_is_still_alive() {
+        let collector = Collector::default();
+        let str = Value::String(Rc::new("four".to_owned()));
+        collector.track(&str);
+        let list = new_list(&collector, vec![str.copy_shallow()]);
+        let dropped = new_list(&collector, vec![Value::Null, Value::Null]);
+
+        drop(dropped);
+
+        assert_eq!(collector.memory_in_use(), 4 + size_of::<Value>());
+
+        drop(list);
+        drop(str);
+
+        assert_eq!(collector.memory_in_use(), 0);
+    }
+}
@@ -1,430 +1,1695 @@
+use std::cell::Cell;
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::io::stdin;
-use std::io::stdout;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
 use std::io::Write;
 use std::rc::Rc;
 
+use crate::analysis::FnVars;
+use crate::compiler::Compiler;
+use crate::convert::*;
+use crate::frame::*;
+use crate::gc::*;
+use crate::lexer::Span;
+use crate::limits::InterruptHandle;
+use crate::limits::Limits;
+use crate::ordered_map::OrderedMap;
 use crate::scope::ScopeManager;
+use crate::streams::Streams;
 use crate::syntax::*;
 use crate::value::*;
 
+/// The number of the deepest frames shown in a traceback.
+pub const MAX_SHOWN_FRAMES: usize = 10;
+
+/// A function call that was in progress when an error was raised.
+#[derive(Debug)]
+pub struct TraceFrame {
+    /// The name of the called function.
+    pub name: String,
+    /// Where the function raised the error or made the next call, if known.
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "in {} at {}", self.name, span),
+            None => write!(f, "in {}", self.name),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum RuntimeError {
     OperationError(OperationError),
+    /// The number of arguments expected, and the number given.
     InvalidArgCount(usize, usize),
     UndefinedIdentifier(String),
     InvalidArgumentType(String, String),
     NoScope,
     IndexOutOfBounds(usize, isize),
+    /// The maximum call depth.
+    StackOverflow(usize),
+    /// The number of bytes of native stack nested calls were allowed to use.
+    NativeStackOverflow(usize),
+    OutOfFuel,
+    /// The number of bytes the program was allowed to hold at once.
+    MemoryLimit(usize),
+    /// A number from the host that does not fit in a script number.
+    NumberOutOfRange(i64),
+    /// An error reported by a host function.
+    HostError(String),
+    /// Reading from or writing to one of the program's streams failed.
+    Io(io::Error),
+    /// The host interrupted the program.
+    Interrupted,
+    /// A string that could not be parsed as a number.
+    InvalidNumber(String),
+    /// A value thrown by the program.
+    Thrown(Value),
+    /// The type of a value used as the condition of an `if` or `while`.
+    InvalidCondition(String),
+    /// The name of a `const` global the program tried to assign to.
+    ConstantAssignment(String),
+    /// A variable, and the function using it, that could refer to a local
+    /// of a calling function, which compiled programs cannot see.
+    CallerVariable(String, String),
+    /// An error that left the program, and the calls it passed through on
+    /// the way, innermost first.
+    Traced(Box<RuntimeError>, Vec<TraceFrame>),
+}
+
+impl RuntimeError {
+    /// Returns the name of the kind of error, as seen by scripts.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::OperationError(_) => "OperationError",
+            Self::InvalidArgCount(..) => "InvalidArgCount",
+            Self::UndefinedIdentifier(_) => "UndefinedIdentifier",
+            Self::InvalidArgumentType(..) => "InvalidArgumentType",
+            Self::NoScope => "NoScope",
+            Self::IndexOutOfBounds(..) => "IndexOutOfBounds",
+            Self::StackOverflow(_) | Self::NativeStackOverflow(_) => {
+                "StackOverflow"
+            }
+            Self::OutOfFuel => "OutOfFuel",
+            Self::MemoryLimit(_) => "MemoryLimit",
+            Self::NumberOutOfRange(_) => "NumberOutOfRange",
+            Self::HostError(_) => "HostError",
+            Self::Io(_) => "Io",
+            Self::Interrupted => "Interrupted",
+            Self::InvalidNumber(_) => "InvalidNumber",
+            Self::Thrown(_) => "Thrown",
+            Self::InvalidCondition(_) => "InvalidCondition",
+            Self::ConstantAssignment(_) => "ConstantAssignment",
+            Self::CallerVariable(..) => "CallerVariable",
+            Self::Traced(err, _) => err.kind(),
+        }
+    }
+
+    /// Returns the error without the calls it passed through.
+    pub fn root(&self) -> &RuntimeError {
+        match self {
+            Self::Traced(err, _) => err,
+            _ => self,
+        }
+    }
+
+    /// Adds the calls the error passed through, innermost first, to those it
+    /// already passed through.
+    pub fn traced(self, frames: Vec<TraceFrame>) -> Self {
+        match self {
+            _ if frames.is_empty() => self,
+            Self::Traced(err, mut inner) => {
+                inner.extend(frames);
+                Self::Traced(err, inner)
+            }
+            _ => Self::Traced(Box::new(self), frames),
+        }
+    }
+
+    /// Returns whether a script may catch the error. The limits set by the
+    /// host cannot be caught, so that a script cannot carry on past them, and
+    /// neither can a program the compiler cannot run as the interpreter would.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self.root(),
+            Self::OutOfFuel
+                | Self::MemoryLimit(_)
+                | Self::Interrupted
+                | Self::CallerVariable(..)
+        )
+    }
+
+    /// Turns the error into the value a `catch` clause receives, given the
+    /// frames it was raised in. A thrown value is received as it is, and any
+    /// other error as an object with its kind, message and stack.
+    pub fn into_error_value(self, stack: Vec<String>) -> Value {
+        let err = match self {
+            Self::Thrown(value) => return value,
+            Self::Traced(err, _) => return err.into_error_value(stack),
+            err => err,
+        };
+
+        let stack = stack
+            .into_iter()
+            .map(|name| Value::String(Rc::new(name)))
+            .collect();
+
+        let fields = [
+            ("kind", Value::String(Rc::new(err.kind().to_owned()))),
+            ("message", Value::String(Rc::new(err.to_string()))),
+            ("stack", Value::List(Rc::new(RefCell::new(stack)))),
+        ];
+
+        let object = fields
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect();
+
+        Value::Object(Rc::new(RefCell::new(object)))
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::OperationError(op_err) => Some(op_err),
+            Self::Io(err) => Some(err),
+            Self::Traced(err, _) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(msg: String) -> Self {
+        Self::HostError(msg)
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(msg: &str) -> Self {
+        Self::HostError(msg.to_owned())
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OperationError(op_err) => write!(f, "{}", op_err),
+            Self::InvalidArgCount(exp, rec) => {
+                write!(f, "Expected {} args, got {}", exp, rec)
+            }
+            Self::UndefinedIdentifier(name) => {
+                write!(f, "Unknown identifier: {}", name)
+            }
+            Self::InvalidArgumentType(expected, got) => {
+                write!(f, "Expected type {expected}, got {got}")
+            }
+            Self::NoScope => write!(f, "All scopes have been popped"),
+            Self::IndexOutOfBounds(length, tried) => write!(
+                f,
+                "Index out of bounds: used index {} on a list of length {}",
+                tried, length
+            ),
+            Self::StackOverflow(max_depth) => write!(
+                f,
+                "Stack overflow: exceeded the maximum call depth of {}",
+                max_depth
+            ),
+            Self::NativeStackOverflow(stack_size) => write!(
+                f,
+                "Stack overflow: nested calls used more than the {} bytes of \
+                 native stack they were allowed",
+                stack_size
+            ),
+            Self::OutOfFuel => write!(
+                f,
+                "Out of fuel: the program took more steps than it was allowed"
+            ),
+            Self::MemoryLimit(max_memory) => write!(
+                f,
+                "Memory limit exceeded: the program held more than {} bytes",
+                max_memory
+            ),
+            Self::NumberOutOfRange(num) => {
+                write!(f, "Number out of range: {} is too large", num)
+            }
+            Self::HostError(msg) => write!(f, "{}", msg),
+            Self::Io(err) => write!(f, "I/O error: {}", err),
+            Self::Interrupted => {
+                write!(f, "Interrupted: the program was stopped by its host")
+            }
+            Self::InvalidNumber(str) => {
+                write!(f, "Invalid number: {:?} is not a number", str)
+            }
+            Self::Thrown(value) => write!(f, "Uncaught exception: {}", value),
+            Self::InvalidCondition(got) => {
+                write!(f, "Expected a boolean condition, got {}", got)
+            }
+            Self::ConstantAssignment(name) => {
+                write!(f, "Cannot assign to the constant {}", name)
+            }
+            Self::CallerVariable(var, func) => write!(
+                f,
+                "{} in {} may refer to a variable of a function calling it, \
+                 which compiled programs cannot see",
+                var, func
+            ),
+            Self::Traced(err, frames) => {
+                write!(f, "{}\nTraceback, most recent call last:", err)?;
+
+                if frames.len() > MAX_SHOWN_FRAMES {
+                    let hidden = frames.len() - MAX_SHOWN_FRAMES;
+                    write!(f, "\n    ... {} more calls", hidden)?;
+                }
+
+                for frame in frames.iter().take(MAX_SHOWN_FRAMES).rev() {
+                    write!(f, "\n    {}", frame)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
 }
 
-enum BodyResult {
-    Return(Value),
-    None,
+/// Lets builtins call back into the running program, such as to apply a
+/// function given by the script to each item of a list, and see where they
+/// were called from.
+pub trait CallContext {
+    /// Calls the function with the given name, which may be defined by the
+    /// program or be a builtin.
+    fn call(&self, name: &str, args: Vec<Value>)
+        -> Result<Value, RuntimeError>;
+
+    /// Returns the value of the variable with the given name as the code
+    /// calling the builtin sees it, or `None` if there is no such variable.
+    /// Compiled programs only see the variables of the calling function and
+    /// the globals.
+    fn lookup(&self, name: &str) -> Option<Value>;
+
+    /// Returns where the builtin was called, or `None` if it was called by
+    /// the host.
+    fn span(&self) -> Option<Span>;
 }
 
-enum FnObj {
+/// The body of a builtin, given the context it is called in and its
+/// arguments.
+pub type BuiltinFn =
+    Rc<dyn Fn(&dyn CallContext, Vec<Value>) -> Result<Value, RuntimeError>>;
+
+pub enum FnObj {
     Builtin {
         param_count: usize,
-        body: Box<dyn Fn(Vec<Value>) -> Result<Value, RuntimeError> + 'static>,
+        body: BuiltinFn,
     },
     Defined {
+        name: String,
         params: Vec<String>,
         body: Vec<Stmt>,
     },
 }
 
-impl FnObj {
-    fn param_count(&self) -> usize {
-        match self {
-            Self::Builtin { param_count, .. } => *param_count,
-            Self::Defined { params, .. } => params.len(),
-        }
-    }
-}
-
 pub struct Interpreter {
     scope: Rc<RefCell<ScopeManager>>,
     funcs: HashMap<String, FnObj>,
+    /// The variables of each defined function.
+    fn_vars: HashMap<String, FnVars>,
+    /// Whether a call each function makes to itself in tail position can
+    /// reuse its frame, worked out the first time one is made.
+    reusable_frames: RefCell<HashMap<String, bool>>,
+    limits: Rc<Limits>,
+    collector: Rc<Collector>,
+    /// The number of calls to defined functions in progress, including those
+    /// made by builtins calling back into the program.
+    depth: Cell<usize>,
+    streams: Rc<Streams>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The context the interpreter calls a builtin in.
+struct BuiltinCall<'a> {
+    interpreter: &'a Interpreter,
+    span: Option<Span>,
+    /// The functions the code calling the builtin can name.
+    fn_names: Option<&'a FnNames>,
+}
+
+impl CallContext for BuiltinCall<'_> {
+    fn call(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let name = callee_name(self.fn_names, name)?;
+        self.interpreter
+            .call_at(name, args, self.span, self.fn_names)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Value> {
+        self.interpreter
+            .scope
+            .borrow()
+            .get(name)
+            .map(Value::copy_shallow)
+    }
+
+    fn span(&self) -> Option<Span> {
+        self.span
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {
+        let mut interpreter = Self {
             scope: Rc::new(RefCell::new(ScopeManager::default())),
             funcs: HashMap::new(),
-        }
+            fn_vars: HashMap::new(),
+            reusable_frames: RefCell::new(HashMap::new()),
+            limits: Rc::new(Limits::default()),
+            collector: Rc::new(Collector::default()),
+            depth: Cell::new(0),
+            streams: Rc::new(Streams::default()),
+        };
+
+        interpreter.define_builtins();
+        interpreter
+    }
+
+    /// Sets the maximum depth of nested function calls, beyond which a
+    /// [`RuntimeError::StackOverflow`] is raised.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.limits.set_max_depth(max_depth);
+    }
+
+    /// Limits the native stack that nested calls in a compiled program may
+    /// use to the given number of bytes, beyond which a
+    /// [`RuntimeError::NativeStackOverflow`] is raised at whatever depth has
+    /// been reached. It should leave room to spare on the thread's stack, which
+    /// the program would otherwise overflow before reaching a large maximum
+    /// depth.
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.limits.set_stack_size(Some(stack_size));
+    }
+
+    /// Limits the program to the given number of steps, beyond which a
+    /// [`RuntimeError::OutOfFuel`] is raised. Every statement run takes one
+    /// step, as does every check of the condition of a `while` loop, whether
+    /// the program is interpreted or compiled.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.limits.set_fuel(Some(fuel));
+    }
+
+    /// Returns the number of steps the program has left, or `None` if it is
+    /// not limited.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.limits.fuel()
+    }
+
+    /// Limits the number of bytes the program may hold at once for strings,
+    /// lists and objects, beyond which a [`RuntimeError::MemoryLimit`] is
+    /// raised. Memory is given back when the values holding it are freed,
+    /// including by the cycle collector.
+    pub fn set_max_memory(&mut self, max_memory: usize) {
+        self.limits.set_max_memory(Some(max_memory));
+    }
+
+    /// Returns a handle that can interrupt the program from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.limits.interrupt_handle()
+    }
+
+    /// Returns the number of bytes the program holds for strings, lists and
+    /// objects.
+    pub fn memory_in_use(&self) -> usize {
+        self.collector.memory_in_use()
+    }
+
+    /// Frees lists and objects that are only kept alive by reference cycles,
+    /// returning how many were freed.
+    pub fn collect_garbage(&self) -> usize {
+        self.collector.collect()
+    }
+
+    /// Returns statistics about the cycle collector.
+    pub fn gc_stats(&self) -> GcStats {
+        self.collector.stats()
+    }
+
+    /// Sends what the program prints to the given stream instead of the
+    /// standard output.
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.streams.set_stdout(stdout);
+    }
+
+    /// Sends the errors the program prints to the given stream instead of the
+    /// standard error.
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.streams.set_stderr(stderr);
+    }
+
+    /// Reads the program's input from the given stream instead of the
+    /// standard input.
+    pub fn set_stdin(&mut self, stdin: impl Read + 'static) {
+        self.streams.set_stdin(stdin);
     }
 
-    fn define_fn(
+    /// Returns the value of the global variable with the given name.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.scope
+            .borrow()
+            .get_global(name)
+            .map(Value::copy_shallow)
+    }
+
+    /// Declares a global variable with the given name, or replaces its value
+    /// if it already exists. Globals are visible to every function.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.scope
+            .borrow_mut()
+            .declare_global(name.to_owned(), value, false);
+    }
+
+    /// Defines a function that scripts can call, implemented in Rust. It is
+    /// given the arguments as they are, which must be as many as
+    /// `param_count`, and a context to call functions of the program with.
+    pub fn define_fn(
         &mut self,
         name: &str,
         param_count: usize,
-        body: impl Fn(Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+        body: impl Fn(&dyn CallContext, Vec<Value>) -> Result<Value, RuntimeError>
+            + 'static,
     ) {
+        self.fn_vars.remove(name);
+        self.reusable_frames.get_mut().clear();
+
         self.funcs.insert(
             name.to_owned(),
             FnObj::Builtin {
                 param_count,
-                body: Box::new(body),
+                body: Rc::new(body),
             },
         );
     }
 
+    /// Defines a function that scripts can call, implemented in Rust. Its
+    /// arguments and result are converted to and from script values, and a
+    /// call with the wrong number or types of arguments is an error.
+    ///
+    /// The value returned by the function counts towards the memory limit,
+    /// like one the program created, and the lists and objects it
+    /// creates are tracked by the cycle collector.
+    pub fn register_fn<Args>(&mut self, name: &str, func: impl HostFn<Args>) {
+        let param_count = func.param_count();
+        let limits = Rc::clone(&self.limits);
+        let collector = Rc::clone(&self.collector);
+
+        self.define_fn(name, param_count, move |_, args| {
+            let value = func.call(args)?;
+            account_new(&limits, &collector, &value)?;
+
+            Ok(value)
+        });
+    }
+
     fn define_builtins(&mut self) {
-        self.define_fn("print", 1, |args| {
-            println!("{}", args[0]);
+        let streams = Rc::clone(&self.streams);
+
+        self.define_fn("print", 1, move |_, args| {
+            writeln!(streams.stdout.borrow_mut(), "{}", args[0])?;
             Ok(Value::Null)
         });
 
-        self.define_fn("prompt", 1, |mut args| {
-            let arg1 = args.remove(0);
+        let streams = Rc::clone(&self.streams);
 
-            let Value::String(msg) = arg1 else {
-                return Err(RuntimeError::InvalidArgumentType(
-                    "string".into(),
-                    args[0].type_name().into(),
-                ));
-            };
+        self.define_fn("eprint", 1, move |_, args| {
+            writeln!(streams.stderr.borrow_mut(), "{}", args[0])?;
+            Ok(Value::Null)
+        });
 
-            print!("{}", msg);
-            stdout().flush().unwrap();
+        let streams = Rc::clone(&self.streams);
 
-            let mut buf = String::new();
-            stdin().read_line(&mut buf).unwrap();
+        self.register_fn(
+            "prompt",
+            move |msg: String| -> Result<_, RuntimeError> {
+                let mut stdout = streams.stdout.borrow_mut();
+                write!(stdout, "{}", msg)?;
+                stdout.flush()?;
 
-            Ok(Value::String(buf.trim().to_owned()))
-        });
+                let mut buf = String::new();
+                streams.stdin.borrow_mut().read_line(&mut buf)?;
 
-        self.define_fn("parseint", 1, |args| match args[0] {
-            Value::String(ref str) => {
-                Ok(Value::Number(str.parse::<i32>().unwrap()))
-            }
-            _ => Err(RuntimeError::InvalidArgumentType(
-                "string".into(),
-                args[0].type_name().into(),
-            )),
-        });
+                Ok(buf.trim().to_owned())
+            },
+        );
 
-        self.define_fn("tostring", 1, |args| {
-            Ok(Value::String(args[0].to_string()))
+        self.register_fn("parseint", |str: String| {
+            str.parse::<i32>()
+                .map_err(|_| RuntimeError::InvalidNumber(str))
         });
 
-        self.define_fn("len", 1, move |mut args| {
-            let value = args.remove(0);
+        let limits = Rc::clone(&self.limits);
+        let collector = Rc::clone(&self.collector);
 
-            let Value::List(list) = value else {
-                return Err(RuntimeError::InvalidArgumentType(
-                    "list".into(),
-                    value.type_name().into(),
-                ));
-            };
+        self.define_fn("tostring", 1, move |_, args| {
+            let value = Value::String(Rc::new(args[0].to_string()));
+            account(&limits, &collector, &value)?;
 
+            Ok(value)
+        });
+
+        self.register_fn(
+            "len",
+            |list: Rc<RefCell<Vec<Value>>>| -> Result<_, RuntimeError> {
+                Ok(list.borrow().len() as i32)
+            },
+        );
+
+        self.register_fn("get", |list: Rc<RefCell<Vec<Value>>>, index: i32| {
             let list = list.borrow();
 
-            Ok(Value::Number(list.len() as i32))
+            list.get(index as usize).map(Value::copy_shallow).ok_or(
+                RuntimeError::IndexOutOfBounds(list.len(), index as isize),
+            )
         });
 
-        self.define_fn("get", 2, move |mut args| {
-            let value = args.remove(0);
+        self.register_fn(
+            "set",
+            |list: Rc<RefCell<Vec<Value>>>,
+             index: i32,
+             value: Value|
+             -> Result<_, RuntimeError> {
+                let mut list = list.borrow_mut();
+                let length = list.len();
 
-            let Value::List(list) = value else {
-                return Err(RuntimeError::InvalidArgumentType(
-                    "list".into(),
-                    value.type_name().into(),
-                ));
-            };
+                *list.get_mut(index as usize).ok_or(
+                    RuntimeError::IndexOutOfBounds(length, index as isize),
+                )? = value;
 
-            let value = args.remove(0);
+                Ok(())
+            },
+        );
 
-            let Value::Number(index) = value else {
-                return Err(RuntimeError::InvalidArgumentType(
-                    "number".into(),
-                    value.type_name().into(),
-                ));
-            };
+        self.define_fn("same", 2, |_, args| {
+            Ok(Value::Boolean(args[0].is_same(&args[1])))
+        });
 
-            let list = list.borrow();
+        self.define_fn("compare", 2, |_, args| {
+            let ordering = args[0]
+                .compare(&args[1])
+                .map_err(RuntimeError::OperationError)?;
 
-            list.get(index as usize).map(Value::copy_shallow).ok_or(
-                RuntimeError::IndexOutOfBounds(list.len(), index as isize),
-            )
+            Ok(Value::Number(ordering as i32))
         });
 
-        self.define_fn("set", 3, move |mut args| {
-            let value = args.remove(0);
+        let limits = Rc::clone(&self.limits);
+        let collector = Rc::clone(&self.collector);
 
-            let Value::List(list) = value else {
-                return Err(RuntimeError::InvalidArgumentType(
-                    "list".into(),
-                    value.type_name().into(),
-                ));
-            };
+        self.define_fn("map", 2, move |ctx, args| {
+            let (items, func) = list_and_fn(args)?;
 
-            let value = args.remove(0);
+            let mapped = items
+                .into_iter()
+                .map(|item| ctx.call(&func, vec![item]))
+                .collect::<Result<Vec<_>, _>>()?;
 
-            let Value::Number(index) = value else {
-                return Err(RuntimeError::InvalidArgumentType(
-                    "number".into(),
-                    value.type_name().into(),
-                ));
-            };
+            new_list(&limits, &collector, mapped)
+        });
+
+        let limits = Rc::clone(&self.limits);
+        let collector = Rc::clone(&self.collector);
+
+        self.define_fn("filter", 2, move |ctx, args| {
+            let (items, func) = list_and_fn(args)?;
+            let mut kept = vec![];
+
+            for item in items {
+                match ctx.call(&func, vec![item.copy_shallow()])? {
+                    Value::Boolean(true) => kept.push(item),
+                    Value::Boolean(false) => {}
+                    value => {
+                        return Err(RuntimeError::InvalidArgumentType(
+                            "boolean".into(),
+                            value.type_name().into(),
+                        ))
+                    }
+                }
+            }
 
-            let value = args.remove(0);
+            new_list(&limits, &collector, kept)
+        });
 
-            let length = list.borrow().len();
+        self.define_fn("each", 2, |ctx, args| {
+            let (items, func) = list_and_fn(args)?;
 
-            *list.borrow_mut().get_mut(index as usize).ok_or(
-                RuntimeError::IndexOutOfBounds(length, index as isize),
-            )? = value;
+            for item in items {
+                ctx.call(&func, vec![item])?;
+            }
 
             Ok(Value::Null)
         });
 
-        self.define_fn("append", 2, move |mut args| {
-            let value = args.remove(0);
+        let limits = Rc::clone(&self.limits);
+        let collector = Rc::clone(&self.collector);
 
-            let Value::List(list) = value else {
-                return Err(RuntimeError::InvalidArgumentType(
-                    "list".into(),
-                    value.type_name().into(),
-                ));
-            };
+        self.define_fn("sort_by", 2, move |ctx, args| {
+            let (items, func) = list_and_fn(args)?;
 
-            let value = args.remove(0);
+            let sorted = merge_sort(items, &mut |a, b| {
+                let args = vec![a.copy_shallow(), b.copy_shallow()];
 
-            list.borrow_mut().push(value);
+                match ctx.call(&func, args)? {
+                    Value::Number(num) => Ok(num.cmp(&0)),
+                    value => Err(RuntimeError::InvalidArgumentType(
+                        "number".into(),
+                        value.type_name().into(),
+                    )),
+                }
+            })?;
 
-            Ok(Value::Null)
+            new_list(&limits, &collector, sorted)
         });
+
+        let collector = Rc::clone(&self.collector);
+
+        self.define_fn("gc", 0, move |_, _| {
+            Ok(Value::Number(collector.collect() as i32))
+        });
+
+        let limits = Rc::clone(&self.limits);
+        let collector = Rc::clone(&self.collector);
+
+        self.register_fn(
+            "append",
+            move |list: Rc<RefCell<Vec<Value>>>,
+                  value: Value|
+                  -> Result<_, RuntimeError> {
+                limits.allocate(size_of::<Value>(), || {
+                    collector.memory_in_use()
+                })?;
+                list.borrow_mut().push(value);
+
+                Ok(())
+            },
+        );
+    }
+
+    pub fn interpret(
+        &mut self,
+        decls: Vec<Decl>,
+        args: Vec<String>,
+    ) -> Result<(), RuntimeError> {
+        let script = self.load(decls);
+        self.run_script(&script, args)
+    }
+
+    /// Runs the top-level statements of the program, with the command-line
+    /// arguments in the global `args` list, then calls `main` with the
+    /// arguments if the program defines it.
+    pub fn run_script(
+        &mut self,
+        script: &[Stmt],
+        args: Vec<String>,
+    ) -> Result<(), RuntimeError> {
+        let cmd_args = self.script_args(args)?;
+
+        let args_list = self.args_list(&cmd_args)?;
+        self.set_global("args", args_list);
+        self.exec_as("<script>", script)?;
+
+        if self.has_fn("main") {
+            self.call("main", cmd_args)?;
+        }
+
+        Ok(())
+    }
+
+    /// Defines the functions declared by the program, replacing any that
+    /// already exist with the same name, and returns its top-level
+    /// statements.
+    pub fn load(&mut self, decls: Vec<Decl>) -> Vec<Stmt> {
+        decls
+            .into_iter()
+            .filter_map(|decl| self.interpret_decl(decl))
+            .collect()
+    }
+
+    /// Returns whether a function with the given name is defined.
+    pub fn has_fn(&self, name: &str) -> bool {
+        self.funcs.contains_key(name)
+    }
+
+    /// Calls the function with the given name, returning its result.
+    pub fn call(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        self.call_at(name, args, None, None)
+    }
+
+    /// Calls the function with the given name, giving a builtin the span of
+    /// the call it is made for, if any, and the functions the code making it
+    /// can name.
+    fn call_at(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+        span: Option<Span>,
+        fn_names: Option<&FnNames>,
+    ) -> Result<Value, RuntimeError> {
+        let func = self.funcs.get(name).ok_or_else(|| {
+            RuntimeError::UndefinedIdentifier(name.to_owned())
+        })?;
+
+        self.call_fn(func, args, span, fn_names)
+    }
+
+    /// Runs the program using the closure-compilation backend instead of
+    /// walking the syntax tree.
+    pub fn interpret_compiled(
+        &mut self,
+        decls: Vec<Decl>,
+        args: Vec<String>,
+    ) -> Result<(), RuntimeError> {
+        let program = Compiler::new(
+            &self.funcs,
+            Rc::clone(&self.limits),
+            Rc::clone(&self.collector),
+        )
+        .compile(&decls)?;
+
+        let cmd_args = self.script_args(args)?;
+
+        program.run_script(self.args_list(&cmd_args)?)?;
+
+        if program.has_fn("main") {
+            program.call("main", cmd_args)?;
+        }
+
+        Ok(())
+    }
+
+    /// Turns the command-line arguments into the strings the script is given.
+    fn script_args(
+        &self,
+        args: Vec<String>,
+    ) -> Result<Vec<Value>, RuntimeError> {
+        args.into_iter()
+            .map(|arg| {
+                let value = Value::String(Rc::new(arg));
+                account(&self.limits, &self.collector, &value)?;
+
+                Ok(value)
+            })
+            .collect()
+    }
+
+    /// Creates the list of command-line arguments given to the script.
+    fn args_list(&self, args: &[Value]) -> Result<Value, RuntimeError> {
+        let args = args.iter().map(Value::copy_shallow).collect();
+        new_list(&self.limits, &self.collector, args)
+    }
+
+    fn call_fn(
+        &self,
+        func: &FnObj,
+        args: Vec<Value>,
+        span: Option<Span>,
+        fn_names: Option<&FnNames>,
+    ) -> Result<Value, RuntimeError> {
+        match func {
+            FnObj::Builtin { param_count, body } => {
+                Self::check_arg_count(*param_count, &args)?;
+
+                let ctx = BuiltinCall {
+                    interpreter: self,
+                    span,
+                    fn_names,
+                };
+                body(&ctx, args)
+            }
+            FnObj::Defined { .. } => {
+                let mut frames = vec![];
+                self.push_frame(&mut frames, func, args)?;
+                self.run_frames(frames)
+            }
+        }
+    }
+
+    /// Runs statements in the global scope, so that the variables they
+    /// declare outlive them, such as input typed into the REPL. Returns the
+    /// value of a `return` among them, or null.
+    pub fn exec(&self, stmts: &[Stmt]) -> Result<Value, RuntimeError> {
+        self.exec_as("<input>", stmts)
+    }
+
+    /// Runs the statements like [`Self::exec`], in a frame with the given
+    /// name.
+    fn exec_as<'a>(
+        &'a self,
+        name: &'a str,
+        stmts: &'a [Stmt],
+    ) -> Result<Value, RuntimeError> {
+        let mut frame = CallFrame::new(name, &[], stmts);
+
+        for stmt in stmts.iter().rev() {
+            frame.tasks.push(Task::Exec(stmt));
+        }
+
+        self.depth.set(self.depth.get() + 1);
+        self.run_frames(vec![frame])
     }
 
-    pub fn interpret(mut self, decls: Vec<Decl>) -> Result<(), RuntimeError> {
-        self.define_builtins();
+    /// Runs the frames until the outermost one returns, then cleans up after
+    /// them.
+    fn run_frames<'a>(
+        &'a self,
+        mut frames: Vec<CallFrame<'a>>,
+    ) -> Result<Value, RuntimeError> {
+        let res = self
+            .run(&mut frames)
+            .map_err(|err| err.traced(Self::traceback(&frames)));
+
+        // Frames are left behind when an error stops the run, so the scopes
+        // they pushed are popped here instead.
+        self.depth.set(self.depth.get() - frames.len());
+
+        for frame in frames.iter_mut().rev() {
+            while frame.scopes > 0 {
+                self.pop_scope(frame)?;
+            }
+        }
+
+        res
+    }
 
-        for decl in decls {
-            self.interpret_decl(decl);
+    fn check_arg_count(
+        param_count: usize,
+        args: &[Value],
+    ) -> Result<(), RuntimeError> {
+        if args.len() != param_count {
+            return Err(RuntimeError::InvalidArgCount(param_count, args.len()));
         }
 
-        let cmd_args = std::env::args()
-            .skip(2)
-            .map(Value::String)
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        match self.funcs.get("main") {
-            Some(main) => self.call_fn(main, cmd_args)?,
-            None => panic!("No main function found!"),
+    /// Pushes a frame for a call to a defined function.
+    fn push_frame<'a>(
+        &self,
+        frames: &mut Vec<CallFrame<'a>>,
+        func: &'a FnObj,
+        args: Vec<Value>,
+    ) -> Result<(), RuntimeError> {
+        let FnObj::Defined { name, params, body } = func else {
+            unreachable!("not a defined function");
         };
 
+        Self::check_arg_count(params.len(), &args)?;
+
+        if self.limits.take_interrupt() {
+            return Err(RuntimeError::Interrupted);
+        }
+
+        let max_depth = self.limits.max_depth();
+
+        if self.depth.get() >= max_depth {
+            return Err(RuntimeError::StackOverflow(max_depth));
+        }
+
+        let mut frame = CallFrame::new(name, params, body);
+        self.enter_call(&mut frame, args)?;
+        frames.push(frame);
+        self.depth.set(self.depth.get() + 1);
+
         Ok(())
     }
 
-    fn interpret_body(
+    /// Returns the names of the deepest frames, innermost first.
+    fn stack_trace(frames: &[CallFrame<'_>]) -> Vec<String> {
+        frames
+            .iter()
+            .rev()
+            .take(MAX_SHOWN_FRAMES)
+            .map(|frame| frame.name.to_owned())
+            .collect()
+    }
+
+    /// Returns the calls the frames are making, innermost first.
+    fn traceback(frames: &[CallFrame<'_>]) -> Vec<TraceFrame> {
+        frames
+            .iter()
+            .rev()
+            .map(|frame| TraceFrame {
+                name: frame.name.to_owned(),
+                span: frame.span,
+            })
+            .collect()
+    }
+
+    /// Declares the parameters of the frame's function and schedules its body.
+    fn enter_call(
         &self,
-        body: &[Stmt],
-    ) -> Result<BodyResult, RuntimeError> {
-        self.scope.borrow_mut().push_scope();
+        frame: &mut CallFrame<'_>,
+        args: Vec<Value>,
+    ) -> Result<(), RuntimeError> {
+        self.push_scope(frame);
 
-        for stmt in body.iter() {
-            if let ret @ BodyResult::Return(_) = self.interpret_stmt(stmt)? {
-                self.scope
-                    .borrow_mut()
-                    .pop_scope()
-                    .map_err(|_| RuntimeError::NoScope)?;
+        for (param, arg) in frame.params.iter().zip(args) {
+            self.scope
+                .borrow_mut()
+                .inner_mut()
+                .ok_or(RuntimeError::NoScope)?
+                .declare(param.clone(), arg);
+        }
+
+        self.enter_body(frame, frame.body);
+
+        Ok(())
+    }
+
+    /// Pops the frame of a function that has returned the given value. The
+    /// value is handed to the calling frame, or returned if there is none.
+    fn leave_frame(
+        &self,
+        frames: &mut Vec<CallFrame<'_>>,
+        value: Value,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let mut frame = frames.pop().expect("no frame to leave");
+        self.depth.set(self.depth.get() - 1);
+
+        while frame.scopes > 0 {
+            self.pop_scope(&mut frame)?;
+        }
+
+        match frames.last_mut() {
+            Some(caller) => {
+                caller.values.push(value);
+                Ok(None)
+            }
+            None => Ok(Some(value)),
+        }
+    }
+
+    /// Returns whether the function can reuse its frame for a call to itself.
+    /// That drops its variables, so it cannot if the function, or any it
+    /// calls, could look up one of them as a variable of a calling function.
+    fn can_reuse_frame(&self, name: &str) -> bool {
+        if let Some(&reusable) = self.reusable_frames.borrow().get(name) {
+            return reusable;
+        }
+
+        let locals = &self.fn_vars[name].locals;
+        let mut reached = HashSet::from([name]);
+        let mut pending = vec![name];
+        let mut reusable = true;
+
+        while let Some(caller) = pending.pop() {
+            let Some(vars) = self.fn_vars.get(caller) else {
+                continue;
+            };
+
+            if vars.free.iter().any(|var| locals.contains(var)) {
+                reusable = false;
+                break;
+            }
 
-                return Ok(ret);
+            for callee in vars.calls.iter() {
+                if reached.insert(callee) {
+                    pending.push(callee);
+                }
             }
         }
 
+        self.reusable_frames
+            .borrow_mut()
+            .insert(name.to_owned(), reusable);
+
+        reusable
+    }
+
+    /// Reruns the frame's function with new arguments, in place of a tail
+    /// call it makes to itself.
+    fn restart_frame(
+        &self,
+        frame: &mut CallFrame<'_>,
+        args: Vec<Value>,
+    ) -> Result<(), RuntimeError> {
+        Self::check_arg_count(frame.params.len(), &args)?;
+
+        frame.tasks.clear();
+        frame.values.clear();
+
+        while frame.scopes > 0 {
+            self.pop_scope(frame)?;
+        }
+
+        self.enter_call(frame, args)
+    }
+
+    fn push_scope(&self, frame: &mut CallFrame<'_>) {
+        self.scope.borrow_mut().push_scope();
+        frame.scopes += 1;
+    }
+
+    fn pop_scope(&self, frame: &mut CallFrame<'_>) -> Result<(), RuntimeError> {
         self.scope
             .borrow_mut()
             .pop_scope()
             .map_err(|_| RuntimeError::NoScope)?;
+        frame.scopes -= 1;
 
-        Ok(BodyResult::None)
+        Ok(())
     }
 
-    fn call_fn(
-        &self,
-        func: &FnObj,
-        args: Vec<Value>,
+    /// Pushes a scope for the body and schedules its statements.
+    fn enter_body<'a>(&self, frame: &mut CallFrame<'a>, body: &'a [Stmt]) {
+        self.push_scope(frame);
+
+        frame.tasks.push(Task::PopScope);
+
+        for stmt in body.iter().rev() {
+            frame.tasks.push(Task::Exec(stmt));
+        }
+    }
+
+    /// Runs tasks until the outermost frame returns. Calls to defined
+    /// functions push a new frame rather than recursing, so only builtins
+    /// grow the native stack.
+    fn run<'a>(
+        &'a self,
+        frames: &mut Vec<CallFrame<'a>>,
     ) -> Result<Value, RuntimeError> {
-        if args.len() != func.param_count() {
-            return Err(RuntimeError::InvalidArgCount(
-                args.len(),
-                func.param_count(),
-            ));
-        }
-
-        match &func {
-            FnObj::Builtin { body, .. } => body(args),
-            FnObj::Defined { params, body } => {
-                self.scope.borrow_mut().push_scope();
-
-                for (param, arg) in params.iter().zip(args.into_iter()) {
-                    self.scope
-                        .borrow_mut()
-                        .inner_mut()
-                        .ok_or(RuntimeError::NoScope)?
-                        .declare(param.clone(), arg);
+        loop {
+            match self.step(frames) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(err) => self.catch(frames, err)?,
+            }
+        }
+    }
+
+    /// Runs the next task of the innermost frame, returning the result of
+    /// the outermost frame once it returns.
+    fn step<'a>(
+        &'a self,
+        frames: &mut Vec<CallFrame<'a>>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let frame = frames.last_mut().expect("no frame to run");
+
+        let Some(task) = frame.tasks.pop() else {
+            // The body ran to completion without returning.
+            return self.leave_frame(frames, Value::Null);
+        };
+
+        if let Some(span) = task.span() {
+            frame.span = Some(span);
+        }
+
+        match task {
+            Task::Exec(stmt) => self.interpret_stmt(frame, stmt)?,
+            Task::Eval(expr) => self.evaluate(frame, expr)?,
+            Task::PopScope => self.pop_scope(frame)?,
+            Task::If(IfStmt { body, .. }) => {
+                if condition(frame.pop_value())? {
+                    self.enter_body(frame, body);
+                }
+            }
+            Task::While(while_stmt) => {
+                // Each check of the condition takes a step, so that a loop
+                // with an empty body still uses up fuel.
+                self.limits.consume_fuel()?;
+
+                if condition(frame.pop_value())? {
+                    if self.limits.take_interrupt() {
+                        return Err(RuntimeError::Interrupted);
+                    }
+
+                    frame.tasks.push(Task::While(while_stmt));
+                    frame.tasks.push(Task::Eval(&while_stmt.cond));
+                    self.enter_body(frame, &while_stmt.body);
+                }
+            }
+            Task::Return => {
+                let value = frame.pop_value();
+
+                // A `finally` body left in the function runs before it
+                // returns, after which the return carries on.
+                if let Some(handler) = frame.take_handler(true) {
+                    self.unwind_to(frame, &handler)?;
+                    frame.values.push(value);
+                    frame.tasks.push(Task::Return);
+
+                    let finally = handler.finally.expect("no finally body");
+                    self.enter_body(frame, finally);
+
+                    return Ok(None);
                 }
 
-                let res =
-                    self.interpret_body(body).map(|body_res| match body_res {
-                        BodyResult::Return(val) => val,
-                        BodyResult::None => Value::Null,
-                    });
+                return self.leave_frame(frames, value);
+            }
+            Task::Handler(handler) => {
+                if let Some(finally) = handler.finally {
+                    self.enter_body(frame, finally);
+                }
+            }
+            Task::Throw(_) => {
+                return Err(RuntimeError::Thrown(frame.pop_value()));
+            }
+            Task::Rethrow(err, _) => return Err(err),
+            Task::TailCall(arg_count, _) => {
+                if self.limits.take_interrupt() {
+                    return Err(RuntimeError::Interrupted);
+                }
 
+                let args = frame.pop_values(arg_count);
+                self.restart_frame(frame, args)?;
+            }
+            Task::Assign(var, _) => {
+                let val = frame.pop_value();
+                self.scope.borrow_mut().set(var, val)?;
+            }
+            Task::Declare(var) => {
+                let val = frame.pop_value();
                 self.scope
                     .borrow_mut()
-                    .pop_scope()
-                    .map_err(|_| RuntimeError::NoScope)?;
+                    .inner_mut()
+                    .ok_or(RuntimeError::NoScope)?
+                    .declare(var.to_owned(), val);
+            }
+            Task::DeclareGlobal(global) => {
+                let val = frame.pop_value();
+                self.scope.borrow_mut().declare_global(
+                    global.var.clone(),
+                    val,
+                    global.constant,
+                );
+            }
+            Task::Call(func, fn_call) => {
+                let args = frame.pop_values(fn_call.args.len());
+
+                match func {
+                    FnObj::Builtin { .. } => {
+                        let value = self.call_fn(
+                            func,
+                            args,
+                            Some(fn_call.span),
+                            fn_call.fn_names.as_deref(),
+                        )?;
+                        frame.values.push(value);
+                    }
+                    FnObj::Defined { .. } => {
+                        self.push_frame(frames, func, args)?
+                    }
+                }
+            }
+            Task::Discard => {
+                frame.pop_value();
+            }
+            Task::Binary(op, _) => {
+                let right = frame.pop_value();
+                let left = frame.pop_value();
+
+                let value = left
+                    .operate(&right, op)
+                    .map_err(RuntimeError::OperationError)?;
+                account(&self.limits, &self.collector, &value)?;
+                frame.values.push(value);
+            }
+            Task::Unary(op, _) => {
+                let value = frame
+                    .pop_value()
+                    .operate_unary(op)
+                    .map_err(RuntimeError::OperationError)?;
+                frame.values.push(value);
+            }
+            Task::Field(name, _) => {
+                let value = frame
+                    .pop_value()
+                    .field(name)
+                    .map_err(RuntimeError::OperationError)?;
+                frame.values.push(value);
+            }
+            Task::Object(fields) => {
+                let values = frame.pop_values(fields.len());
+
+                let mut object = OrderedMap::new();
+
+                for ((name, _), value) in fields.iter().zip(values) {
+                    object.insert(name.clone(), value);
+                }
 
-                res
+                let value = Value::Object(Rc::new(RefCell::new(object)));
+                account(&self.limits, &self.collector, &value)?;
+                frame.values.push(value);
             }
+            Task::List(count) => {
+                let list = frame.pop_values(count);
+
+                let value = Value::List(Rc::new(RefCell::new(list)));
+                account(&self.limits, &self.collector, &value)?;
+                frame.values.push(value);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Hands the error to the innermost `try` statement, unwinding the frames
+    /// and tasks above it. The error is returned if nothing can catch it.
+    fn catch<'a>(
+        &'a self,
+        frames: &mut Vec<CallFrame<'a>>,
+        err: RuntimeError,
+    ) -> Result<(), RuntimeError> {
+        if !err.is_catchable() {
+            return Err(err);
+        }
+
+        let Some(depth) = frames.iter().rposition(CallFrame::in_handler) else {
+            return Err(err);
+        };
+
+        let stack = Self::stack_trace(frames);
+        let trace = Self::traceback(&frames[depth + 1..]);
+
+        while frames.len() > depth + 1 {
+            let mut frame = frames.pop().expect("no frame to unwind");
+            self.depth.set(self.depth.get() - 1);
+
+            while frame.scopes > 0 {
+                self.pop_scope(&mut frame)?;
+            }
+        }
+
+        let frame = frames.last_mut().expect("no frame to catch in");
+        let handler = frame.take_handler(false).expect("no handler");
+        self.unwind_to(frame, &handler)?;
+
+        let Some(catch) = handler.catch else {
+            // Without a catch clause, the error carries on once the finally
+            // body has run.
+            frame
+                .tasks
+                .push(Task::Rethrow(err.traced(trace), frame.span));
+
+            let finally = handler.finally.expect("no finally body");
+            self.enter_body(frame, finally);
+
+            return Ok(());
+        };
+
+        // An error in the catch clause still runs the finally body.
+        if handler.finally.is_some() {
+            frame.tasks.push(Task::Handler(Handler {
+                catch: None,
+                ..handler
+            }));
+        }
+
+        let value = caught_value(&self.limits, &self.collector, err, stack)?;
+
+        self.push_scope(frame);
+        frame.tasks.push(Task::PopScope);
+
+        self.scope
+            .borrow_mut()
+            .inner_mut()
+            .ok_or(RuntimeError::NoScope)?
+            .declare(catch.var.clone(), value);
+
+        self.enter_body(frame, &catch.body);
+
+        Ok(())
+    }
+
+    /// Drops the values and pops the scopes the frame gained since the
+    /// handler's `try` statement started.
+    fn unwind_to(
+        &self,
+        frame: &mut CallFrame<'_>,
+        handler: &Handler<'_>,
+    ) -> Result<(), RuntimeError> {
+        frame.values.truncate(handler.values);
+
+        while frame.scopes > handler.scopes {
+            self.pop_scope(frame)?;
         }
+
+        Ok(())
     }
 
-    fn interpret_decl(&mut self, decl: Decl) {
+    /// Defines the function the declaration declares, or returns it if it is
+    /// a top-level statement.
+    fn interpret_decl(&mut self, decl: Decl) -> Option<Stmt> {
         match decl {
             Decl::FnDecl(fn_decl) => {
+                self.fn_vars.insert(
+                    fn_decl.name.clone(),
+                    FnVars::new(&fn_decl.params, &fn_decl.body),
+                );
+                self.reusable_frames.get_mut().clear();
+
                 self.funcs.insert(
-                    fn_decl.name,
+                    fn_decl.name.clone(),
                     FnObj::Defined {
+                        name: fn_decl.name,
                         params: fn_decl.params,
                         body: fn_decl.body,
                     },
                 );
+
+                None
             }
+            Decl::Stmt(stmt) => Some(stmt),
+            // Imports are resolved by the module loader before the program
+            // is loaded.
+            Decl::Import(_) => None,
         }
     }
 
-    fn interpret_stmt(&self, stmt: &Stmt) -> Result<BodyResult, RuntimeError> {
-        match stmt {
-            Stmt::FnCall(FnCall { name, args }) => {
-                let func = self
-                    .funcs
-                    .get(name)
-                    .ok_or(RuntimeError::UndefinedIdentifier(name.clone()))?;
-                self.call_fn(
-                    func,
-                    args.iter()
-                        .map(|arg| self.evaluate(arg))
-                        .collect::<Result<Vec<_>, _>>()?,
-                )?;
-                Ok(BodyResult::None)
-            }
-            Stmt::If(IfStmt { cond, body }) => {
-                let Value::Boolean(cond_val) = self.evaluate(cond)? else {
-                    panic!("IfStmt must have boolean as condition!");
-                };
+    /// Schedules the tasks that run the statement.
+    fn interpret_stmt<'a>(
+        &'a self,
+        frame: &mut CallFrame<'a>,
+        stmt: &'a Stmt,
+    ) -> Result<(), RuntimeError> {
+        self.limits.consume_fuel()?;
 
-                if cond_val {
-                    self.interpret_body(body)
-                } else {
-                    Ok(BodyResult::None)
-                }
+        match stmt {
+            Stmt::FnCall(fn_call) => {
+                frame.tasks.push(Task::Discard);
+                self.schedule_call(frame, fn_call)?;
+            }
+            Stmt::If(if_stmt) => {
+                frame.tasks.push(Task::If(if_stmt));
+                frame.tasks.push(Task::Eval(&if_stmt.cond));
+            }
+            Stmt::While(while_stmt) => {
+                frame.tasks.push(Task::While(while_stmt));
+                frame.tasks.push(Task::Eval(&while_stmt.cond));
+            }
+            // A call inside a try statement must finish before the statement
+            // does, so it cannot replace the frame.
+            Stmt::Return(ReturnStmt {
+                expr:
+                    Expr::FnCall(FnCall {
+                        name, args, span, ..
+                    }),
+            }) if name == frame.name
+                && !frame.in_handler()
+                && self.can_reuse_frame(name) =>
+            {
+                frame.tasks.push(Task::TailCall(args.len(), *span));
+                Self::schedule_exprs(frame, args);
             }
-            Stmt::While(WhileStmt { cond, body }) => loop {
-                let Value::Boolean(result) = self.evaluate(cond)? else {
-                    panic!("WhileStmt must have boolean as condition!");
-                };
-
-                if !result {
-                    return Ok(BodyResult::None);
-                }
-
-                if let result @ BodyResult::Return(_) =
-                    self.interpret_body(body)?
-                {
-                    return Ok(result);
-                }
-            },
             Stmt::Return(ReturnStmt { expr }) => {
-                Ok(BodyResult::Return(self.evaluate(expr)?))
+                frame.tasks.push(Task::Return);
+                frame.tasks.push(Task::Eval(expr));
             }
-            Stmt::Assign(AssignStmt { var, val }) => {
-                let val = self.evaluate(val)?;
-                self.scope
-                    .borrow_mut()
-                    .inner_mut()
-                    .ok_or(RuntimeError::NoScope)?
-                    .set(var, val)
-                    .map_err(|_| RuntimeError::NoScope)?;
-                Ok(BodyResult::None)
+            Stmt::Assign(AssignStmt { var, val, span }) => {
+                frame.tasks.push(Task::Assign(var, *span));
+                frame.tasks.push(Task::Eval(val));
             }
             Stmt::Decl(DeclStmt { var, val }) => {
-                let val = self.evaluate(val)?;
-                self.scope
-                    .borrow_mut()
-                    .inner_mut()
-                    .ok_or(RuntimeError::NoScope)?
-                    .declare(var.clone(), val);
-                Ok(BodyResult::None)
+                frame.tasks.push(Task::Declare(var));
+                frame.tasks.push(Task::Eval(val));
+            }
+            Stmt::Global(global) => {
+                frame.tasks.push(Task::DeclareGlobal(global));
+                frame.tasks.push(Task::Eval(&global.val));
+            }
+            Stmt::Throw(ThrowStmt { expr, span }) => {
+                frame.tasks.push(Task::Throw(*span));
+                frame.tasks.push(Task::Eval(expr));
+            }
+            Stmt::Try(TryStmt {
+                body,
+                catch,
+                finally,
+            }) => {
+                frame.tasks.push(Task::Handler(Handler {
+                    catch: catch.as_ref(),
+                    finally: finally.as_deref(),
+                    values: frame.values.len(),
+                    scopes: frame.scopes,
+                }));
+                self.enter_body(frame, body);
             }
         }
+
+        Ok(())
     }
 
-    fn evaluate(&self, expr: &Expr) -> Result<Value, RuntimeError> {
-        match expr {
-            Expr::Identfier(name) => Ok(self
+    /// Pushes the value of the expression if it is immediately known, or
+    /// otherwise schedules the tasks that produce it.
+    fn evaluate<'a>(
+        &'a self,
+        frame: &mut CallFrame<'a>,
+        expr: &'a Expr,
+    ) -> Result<(), RuntimeError> {
+        let value = match expr {
+            Expr::Identfier(name, _) => self
                 .scope
                 .borrow()
-                .inner()
-                .ok_or(RuntimeError::NoScope)?
                 .get(name)
                 .ok_or(RuntimeError::UndefinedIdentifier(name.clone()))?
-                .copy_shallow()),
-            Expr::NumberLiteral(num) => Ok(Value::Number(*num)),
-            Expr::BooleanLiteral(bool) => Ok(Value::Boolean(*bool)),
-            Expr::NullLiteral => Ok(Value::Null),
-            Expr::StringLiteral(str) => Ok(Value::String(str.clone())),
-            Expr::FnCall(FnCall { name, args }) => {
-                let func = self
-                    .funcs
-                    .get(name)
-                    .ok_or(RuntimeError::UndefinedIdentifier(name.clone()))?;
-                let res = self.call_fn(
-                    func,
-                    args.iter()
-                        .map(|arg| self.evaluate(arg))
-                        .collect::<Result<Vec<_>, _>>()?,
-                )?;
-                Ok(res)
+                .copy_shallow(),
+            Expr::NumberLiteral(num) => Value::Number(*num),
+            Expr::BooleanLiteral(bool) => Value::Boolean(*bool),
+            Expr::NullLiteral => Value::Null,
+            Expr::StringLiteral(str) => {
+                let value = Value::String(Rc::new(str.clone()));
+                account(&self.limits, &self.collector, &value)?;
+                value
             }
+            Expr::FnCall(fn_call) => return self.schedule_call(frame, fn_call),
             Expr::Binary(bin_expr) => {
-                let left = self.evaluate(&bin_expr.left)?;
-                let right = self.evaluate(&bin_expr.right)?;
-
-                Ok(left
-                    .operate(&right, bin_expr.op)
-                    .map_err(RuntimeError::OperationError))?
+                frame.tasks.push(Task::Binary(bin_expr.op, bin_expr.span));
+                frame.tasks.push(Task::Eval(&bin_expr.right));
+                frame.tasks.push(Task::Eval(&bin_expr.left));
+                return Ok(());
             }
             Expr::Unary(unary_expr) => {
-                let expr = self.evaluate(&unary_expr.expr)?;
-
-                Ok(expr
-                    .operate_unary(unary_expr.op)
-                    .map_err(RuntimeError::OperationError))?
+                frame
+                    .tasks
+                    .push(Task::Unary(unary_expr.op, unary_expr.span));
+                frame.tasks.push(Task::Eval(&unary_expr.expr));
+                return Ok(());
             }
             Expr::ObjectLiteral(fields) => {
-                let mut object = HashMap::new();
+                frame.tasks.push(Task::Object(fields));
 
-                for (name, expr) in fields {
-                    object.insert(name.clone(), self.evaluate(expr)?);
+                for (_, expr) in fields.iter().rev() {
+                    frame.tasks.push(Task::Eval(expr));
                 }
 
-                Ok(Value::Object(Rc::new(RefCell::new(object))))
+                return Ok(());
             }
             Expr::ListLiteral(values) => {
-                let list = values
-                    .iter()
-                    .map(|expr| self.evaluate(expr))
-                    .collect::<Result<Vec<_>, _>>()?;
-
-                Ok(Value::List(Rc::new(RefCell::new(list))))
+                frame.tasks.push(Task::List(values.len()));
+                Self::schedule_exprs(frame, values);
+                return Ok(());
+            }
+            Expr::FieldAccess(FieldAccess { obj, field, span }) => {
+                frame.tasks.push(Task::Field(field, *span));
+                frame.tasks.push(Task::Eval(obj));
+                return Ok(());
             }
-            Expr::FieldAccess(FieldAccess { .. }) => todo!(),
+        };
+
+        frame.values.push(value);
+
+        Ok(())
+    }
+
+    /// Schedules a call to the named function after its arguments.
+    fn schedule_call<'a>(
+        &'a self,
+        frame: &mut CallFrame<'a>,
+        fn_call: &'a FnCall,
+    ) -> Result<(), RuntimeError> {
+        let func = self
+            .funcs
+            .get(&fn_call.name)
+            .ok_or(RuntimeError::UndefinedIdentifier(fn_call.name.clone()))?;
+
+        frame.tasks.push(Task::Call(func, fn_call));
+        Self::schedule_exprs(frame, &fn_call.args);
+
+        Ok(())
+    }
+
+    /// Schedules the expressions to be evaluated in order.
+    fn schedule_exprs<'a>(frame: &mut CallFrame<'a>, exprs: &'a [Expr]) {
+        for expr in exprs.iter().rev() {
+            frame.tasks.push(Task::Eval(expr));
+        }
+    }
+}
+
+/// Takes the value of an `if` or `while` condition, which must be a boolean.
+pub fn condition(value: Value) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Boolean(bool) => Ok(bool),
+        _ => Err(RuntimeError::InvalidCondition(value.type_name().to_owned())),
+    }
+}
+
+/// Takes the items of a list and the name of the function to apply to them
+/// from the arguments of a builtin. The items are copied out, so the function
+/// is free to change the list.
+fn list_and_fn(args: Vec<Value>) -> Result<(Vec<Value>, String), RuntimeError> {
+    let mut args = args.into_iter();
+    let items = Vec::<Value>::from_value(args.next().expect("no list"))?;
+    let func = String::from_value(args.next().expect("no function name"))?;
+
+    Ok((items, func))
+}
+
+/// Creates a list from the items built by a builtin, accounting for it like a
+/// list literal.
+fn new_list(
+    limits: &Limits,
+    collector: &Collector,
+    items: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let value = Value::List(Rc::new(RefCell::new(items)));
+    account(limits, collector, &value)?;
+
+    Ok(value)
+}
+
+/// Returns the name that the function a builtin is given the name of is
+/// known by, given the functions that the code calling the builtin can name.
+pub(crate) fn callee_name<'a>(
+    fn_names: Option<&'a FnNames>,
+    name: &'a str,
+) -> Result<&'a str, RuntimeError> {
+    match fn_names {
+        Some(fn_names) => fn_names
+            .resolve(name)
+            .ok_or_else(|| RuntimeError::UndefinedIdentifier(name.to_owned())),
+        None => Ok(name),
+    }
+}
+
+/// Accounts for a string, list or object the program created, counting it
+/// towards the memory limit and registering it with the collector.
+pub(crate) fn account(
+    limits: &Limits,
+    collector: &Collector,
+    value: &Value,
+) -> Result<(), RuntimeError> {
+    limits.allocate(value.heap_size(), || collector.memory_in_use())?;
+    collector.track(value);
+
+    Ok(())
+}
+
+/// Turns a caught error into the value its catch clause receives, accounting
+/// for the object made for an error raised by the interpreter. A thrown value
+/// was accounted for when the program created it, so it is left alone.
+pub(crate) fn caught_value(
+    limits: &Limits,
+    collector: &Collector,
+    err: RuntimeError,
+    stack: Vec<String>,
+) -> Result<Value, RuntimeError> {
+    let is_thrown = matches!(err.root(), RuntimeError::Thrown(_));
+    let value = err.into_error_value(stack);
+
+    if !is_thrown {
+        account_new(limits, collector, &value)?;
+    }
+
+    Ok(value)
+}
+
+/// Accounts for a value made outside of the program like one it created,
+/// along with the values it holds. Lists and objects that are referenced from
+/// elsewhere already existed, so they and their contents are left alone.
+fn account_new(
+    limits: &Limits,
+    collector: &Collector,
+    value: &Value,
+) -> Result<(), RuntimeError> {
+    let is_new = match value {
+        Value::List(list) => Rc::strong_count(list) == 1,
+        Value::Object(obj) => Rc::strong_count(obj) == 1,
+        _ => true,
+    };
+
+    if !is_new {
+        return Ok(());
+    }
+
+    account(limits, collector, value)?;
+
+    match value {
+        Value::List(list) => list
+            .borrow()
+            .iter()
+            .try_for_each(|item| account_new(limits, collector, item)),
+        Value::Object(obj) => obj
+            .borrow()
+            .values()
+            .try_for_each(|field| account_new(limits, collector, field)),
+        _ => Ok(()),
+    }
+}
+
+/// Sorts the items with a comparison that may fail, keeping equal items in
+/// their original order. Unlike the standard library's sorts, this stays
+/// well-behaved when the comparison is not a consistent ordering, which a
+/// script's comparison function need not be.
+fn merge_sort(
+    mut items: Vec<Value>,
+    compare: &mut impl FnMut(&Value, &Value) -> Result<Ordering, RuntimeError>,
+) -> Result<Vec<Value>, RuntimeError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut sorted = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if compare(a, b)?.is_gt() {
+            sorted.extend(right.next());
+        } else {
+            sorted.extend(left.next());
         }
     }
+
+    sorted.extend(left);
+    sorted.extend(right);
+
+    Ok(sorted)
 }
@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+use std::fmt;
+
+use crate::parser::ParseError;
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum TokenKind {
@@ -16,6 +19,7 @@ pub enum TokenKind {
     Semicolon,
     Colon,
     Equals,
+    DoubleEquals,
     Period,
 
     Plus,
@@ -31,22 +35,45 @@ pub enum TokenKind {
     FnKeyword,
     StructKeyword,
     LetKeyword,
+    ConstKeyword,
+    PubKeyword,
+    ImportKeyword,
+    AsKeyword,
     ReturnKeyword,
     IfKeyword,
     WhileKeyword,
+    ThrowKeyword,
+    TryKeyword,
+    CatchKeyword,
+    FinallyKeyword,
 
     EndOfFile,
 }
 
-#[derive(Debug)]
+/// A position in the source code, counting lines and columns from one.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub text: String,
     pub kind: TokenKind,
+    /// Where the token starts.
+    pub span: Span,
 }
 
 impl Token {
-    fn new(text: String, kind: TokenKind) -> Self {
-        Self { text, kind }
+    fn new(text: String, kind: TokenKind, span: Span) -> Self {
+        Self { text, kind, span }
     }
 }
 
@@ -55,6 +82,10 @@ pub struct Lexer {
     tokens: Vec<Token>,
     position: usize,
     length: usize,
+    /// The line of the current character.
+    line: usize,
+    /// The position of the first character of the current line.
+    line_start: usize,
     symbols: HashMap<char, TokenKind>,
     keywords: HashMap<String, TokenKind>,
 }
@@ -91,12 +122,20 @@ impl Lexer {
             keywords.insert("fn".to_owned(), TokenKind::FnKeyword);
             keywords.insert("struct".to_owned(), TokenKind::StructKeyword);
             keywords.insert("let".to_owned(), TokenKind::LetKeyword);
+            keywords.insert("const".to_owned(), TokenKind::ConstKeyword);
+            keywords.insert("pub".to_owned(), TokenKind::PubKeyword);
+            keywords.insert("import".to_owned(), TokenKind::ImportKeyword);
+            keywords.insert("as".to_owned(), TokenKind::AsKeyword);
             keywords.insert("return".to_owned(), TokenKind::ReturnKeyword);
             keywords.insert("true".to_owned(), TokenKind::TrueKeyword);
             keywords.insert("false".to_owned(), TokenKind::FalseKeyword);
             keywords.insert("null".to_owned(), TokenKind::NullKeyword);
             keywords.insert("if".to_owned(), TokenKind::IfKeyword);
             keywords.insert("while".to_owned(), TokenKind::WhileKeyword);
+            keywords.insert("throw".to_owned(), TokenKind::ThrowKeyword);
+            keywords.insert("try".to_owned(), TokenKind::TryKeyword);
+            keywords.insert("catch".to_owned(), TokenKind::CatchKeyword);
+            keywords.insert("finally".to_owned(), TokenKind::FinallyKeyword);
 
             keywords
         };
@@ -105,7 +144,9 @@ impl Lexer {
             source: source.chars().collect::<Vec<char>>(),
             tokens: vec![],
             position: 0,
-            length: source.len(),
+            length: source.chars().count(),
+            line: 1,
+            line_start: 0,
             symbols,
             keywords,
         }
@@ -119,21 +160,39 @@ impl Lexer {
         }
     }
 
+    fn peek(&self) -> Option<char> {
+        self.source.get(self.position + 1).copied()
+    }
+
     fn advance(&mut self) {
+        if self.current() == Some('\n') {
+            self.line += 1;
+            self.line_start = self.position + 1;
+        }
+
         self.position += 1;
     }
 
-    fn scan_string(&mut self) {
+    /// Returns the position of the current character.
+    fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.position - self.line_start + 1,
+        }
+    }
+
+    fn scan_string(&mut self) -> Result<(), ParseError> {
         let start = self.position;
+        let span = self.span();
 
         self.advance();
 
-        while let Some(current) = self.current() {
-            if current == '"' {
-                break;
+        loop {
+            match self.current() {
+                Some('"') => break,
+                Some(_) => self.advance(),
+                None => return Err(ParseError::UnterminatedString(span)),
             }
-
-            self.advance();
         }
 
         self.advance();
@@ -145,11 +204,14 @@ impl Lexer {
             .take(self.position - start)
             .collect::<String>();
 
-        self.tokens.push(Token::new(text, TokenKind::String))
+        self.tokens.push(Token::new(text, TokenKind::String, span));
+
+        Ok(())
     }
 
     fn scan_number(&mut self) {
         let start = self.position;
+        let span = self.span();
 
         while let Some(current) = self.current() {
             if !current.is_ascii_digit() {
@@ -166,11 +228,12 @@ impl Lexer {
             .take(self.position - start)
             .collect::<String>();
 
-        self.tokens.push(Token::new(text, TokenKind::Number))
+        self.tokens.push(Token::new(text, TokenKind::Number, span))
     }
 
     fn scan_identifier(&mut self) {
         let start = self.position;
+        let span = self.span();
 
         while let Some(current) = self.current() {
             if !current.is_ascii_alphabetic() && current != '_' {
@@ -189,7 +252,7 @@ impl Lexer {
 
         let kind = self.keywords.get(&text).unwrap_or(&TokenKind::Identifer);
 
-        self.tokens.push(Token::new(text, *kind));
+        self.tokens.push(Token::new(text, *kind, span));
     }
 
     fn skip_whitespace(&mut self) {
@@ -202,7 +265,7 @@ impl Lexer {
         }
     }
 
-    pub fn scan_tokens(mut self) -> Vec<Token> {
+    pub fn scan_tokens(mut self) -> Result<Vec<Token>, ParseError> {
         while let Some(current) = self.current() {
             if current.is_ascii_digit() {
                 self.scan_number();
@@ -210,24 +273,36 @@ impl Lexer {
                 self.scan_identifier();
             } else if current.is_ascii_whitespace() {
                 self.skip_whitespace();
+            } else if current == '=' && self.peek() == Some('=') {
+                self.tokens.push(Token::new(
+                    "==".to_owned(),
+                    TokenKind::DoubleEquals,
+                    self.span(),
+                ));
+                self.advance();
+                self.advance();
             } else if self.symbols.contains_key(&current) {
                 self.tokens.push(Token::new(
                     self.source[self.position..self.position + 1]
                         .iter()
                         .collect::<String>(),
                     self.symbols[&current],
+                    self.span(),
                 ));
                 self.advance();
             } else if current == '"' {
-                self.scan_string();
+                self.scan_string()?;
             } else {
-                panic!("Invalid char: {}", current);
+                return Err(ParseError::InvalidChar(current, self.span()));
             }
         }
 
-        self.tokens
-            .push(Token::new("\0".to_owned(), TokenKind::EndOfFile));
+        self.tokens.push(Token::new(
+            "\0".to_owned(),
+            TokenKind::EndOfFile,
+            self.span(),
+        ));
 
-        self.tokens
+        Ok(self.tokens)
     }
 }
@@ -0,0 +1,34 @@
+//! R-Lang as a library, for hosting scripts inside a Rust program.
+//!
+//! The [`Engine`] loads source code, calls the functions it defines and
+//! exchanges [`Value`]s with them.
+
+mod analysis;
+mod compiler;
+pub mod convert;
+pub mod engine;
+mod frame;
+pub mod gc;
+pub mod interpreter;
+pub mod lexer;
+pub mod limits;
+pub mod modules;
+pub mod optimizer;
+pub mod ordered_map;
+pub mod parser;
+pub mod prelude;
+pub mod printing;
+mod scope;
+pub mod streams;
+pub mod syntax;
+pub mod value;
+
+pub use convert::FromValue;
+pub use convert::IntoValue;
+pub use engine::Engine;
+pub use engine::EngineError;
+pub use interpreter::CallContext;
+pub use interpreter::RuntimeError;
+pub use limits::InterruptHandle;
+pub use streams::SharedBuffer;
+pub use value::Value;
@@ -0,0 +1,154 @@
+use std::cell::Cell;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::interpreter::RuntimeError;
+
+/// The call depth a program may reach unless configured otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// Bounds on the resources a running program may use, shared by the
+/// interpreter and the compiled program so that the host can adjust and
+/// inspect them.
+pub struct Limits {
+    /// The maximum depth of nested function calls.
+    max_depth: Cell<usize>,
+
+    /// The number of bytes of native stack that nested calls in a compiled
+    /// program may use, if it is limited.
+    stack_size: Cell<Option<usize>>,
+
+    /// The number of steps the program may still take, if it is limited.
+    fuel: Cell<Option<u64>>,
+
+    /// The number of bytes the program may hold at once for the contents of
+    /// strings, lists and objects, if it is limited.
+    max_memory: Cell<Option<usize>>,
+
+    /// The number of bytes in use when memory was last measured, plus those
+    /// allocated since. Memory freed since then is only taken off when it is
+    /// measured again.
+    memory: Cell<usize>,
+
+    /// Set by an [`InterruptHandle`] to stop the program.
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: Cell::new(DEFAULT_MAX_DEPTH),
+            stack_size: Cell::new(None),
+            fuel: Cell::new(None),
+            max_memory: Cell::new(None),
+            memory: Cell::new(0),
+            interrupted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Limits {
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.get()
+    }
+
+    pub fn set_max_depth(&self, max_depth: usize) {
+        self.max_depth.set(max_depth);
+    }
+
+    pub fn stack_size(&self) -> Option<usize> {
+        self.stack_size.get()
+    }
+
+    pub fn set_stack_size(&self, stack_size: Option<usize>) {
+        self.stack_size.set(stack_size);
+    }
+
+    /// Returns the number of steps left, or `None` if there is no limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel.get()
+    }
+
+    pub fn set_fuel(&self, fuel: Option<u64>) {
+        self.fuel.set(fuel);
+    }
+
+    /// Uses up one step of fuel, failing if none is left.
+    pub fn consume_fuel(&self) -> Result<(), RuntimeError> {
+        match self.fuel.get() {
+            Some(0) => Err(RuntimeError::OutOfFuel),
+            Some(fuel) => {
+                self.fuel.set(Some(fuel - 1));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_max_memory(&self, max_memory: Option<usize>) {
+        self.max_memory.set(max_memory);
+    }
+
+    /// Accounts for the given number of newly allocated bytes, failing if
+    /// they take the program over its memory limit. When the count goes over,
+    /// the memory in use is measured with `measure`, as some of what was
+    /// counted may have been freed since.
+    pub fn allocate(
+        &self,
+        bytes: usize,
+        measure: impl FnOnce() -> usize,
+    ) -> Result<(), RuntimeError> {
+        let Some(max_memory) = self.max_memory.get() else {
+            return Ok(());
+        };
+
+        let mut memory = self.memory.get().saturating_add(bytes);
+
+        if memory > max_memory {
+            memory = measure().saturating_add(bytes);
+        }
+
+        self.memory.set(memory);
+
+        if memory > max_memory {
+            return Err(RuntimeError::MemoryLimit(max_memory));
+        }
+
+        Ok(())
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupted: Arc::clone(&self.interrupted),
+        }
+    }
+
+    /// Returns whether the program has been interrupted, clearing the
+    /// request so that it only stops one run.
+    pub fn take_interrupt(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+            && self.interrupted.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Stops a running program from another thread. The program checks for an
+/// interrupt whenever it calls a function or repeats a loop, and stops with
+/// a [`RuntimeError::Interrupted`].
+#[derive(Clone)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    /// Asks the program to stop. If it is not running, the next run stops
+    /// as soon as it starts.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+    }
+
+    /// Withdraws an interrupt that the program has not acted on yet.
+    pub fn reset(&self) {
+        self.interrupted.store(false, Ordering::Relaxed);
+    }
+}
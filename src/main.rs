@@ -1,91 +1,239 @@
 use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
 
-use interpreter::*;
-use lexer::*;
-use parser::*;
-use value::OperationError;
-
-mod interpreter;
-mod lexer;
-mod parser;
-mod printing;
-mod scope;
-mod syntax;
-mod value;
-
-fn report_parse_err(msg: impl Into<String>) -> ! {
-    panic!("Parse error: {}", msg.into());
+use rlang::interpreter::*;
+use rlang::lexer::*;
+use rlang::modules::ModuleLoader;
+use rlang::optimizer::Optimizer;
+use rlang::parser::*;
+use rlang::prelude;
+
+mod repl;
+
+use repl::Repl;
+
+fn report_parse_err(msg: impl Into<String>) -> ExitCode {
+    eprintln!("Parse error: {}", msg.into());
+    ExitCode::FAILURE
 }
 
-fn report_runtime_err(msg: impl Into<String>) -> ! {
-    panic!("Runtime error: {}", msg.into());
+fn report_module_err(msg: impl Into<String>) -> ExitCode {
+    eprintln!("Module error: {}", msg.into());
+    ExitCode::FAILURE
 }
 
-fn main() -> std::io::Result<()> {
-    let source = match std::env::args().collect::<Vec<_>>().get(1) {
-        Some(path) => fs::read_to_string(format!("./{}", path))?,
-        None => panic!("Must provide a path to the program"),
-    };
+fn report_runtime_err(msg: impl Into<String>) -> ExitCode {
+    eprintln!("Runtime error: {}", msg.into());
+    ExitCode::FAILURE
+}
 
-    let lexer = Lexer::new(source);
-    let tokens = lexer.scan_tokens();
+/// Reports a mistake in the command line, which exits with a different code
+/// than errors in the program.
+fn report_usage_err(msg: impl Into<String>) -> ExitCode {
+    eprintln!("Usage error: {}", msg.into());
+    ExitCode::from(2)
+}
 
-    let parser = Parser::new(tokens);
+/// Options given on the command line before the path to the program.
+#[derive(Default)]
+struct Options {
+    /// Runs the program with the closure-compilation backend.
+    compile: bool,
 
-    let decls = match parser.parse() {
-        Ok(decls) => decls,
-        Err(parse_err) => match parse_err {
-            ParseError::EndOfFile => {
-                report_parse_err("Expected token but reached end of file");
-            }
-            ParseError::ExpectedToken(exp, rec) => {
-                report_parse_err(format!("Expected {:?}, got {:?}", exp, rec));
-            }
-        },
-    };
+    /// Optimizes the syntax tree before running it.
+    optimize: bool,
+
+    /// Overrides the maximum depth of nested function calls.
+    max_depth: Option<usize>,
+
+    /// Limits the number of steps the program may take.
+    fuel: Option<u64>,
+
+    /// Limits the number of bytes the program may hold at once.
+    max_memory: Option<usize>,
+
+    /// Leaves out the standard library that is loaded before the program.
+    no_prelude: bool,
+}
 
-    let interpreter = Interpreter::new();
-
-    match interpreter.interpret(decls) {
-        Ok(()) => Ok(()),
-        Err(run_err) => match run_err {
-            RuntimeError::OperationError(op_err) => match op_err {
-                OperationError::InvalidBinary(left, op, right) => {
-                    report_runtime_err(format!(
-                        "Cannot use binary operator {:?} on types {} and {}",
-                        op,
-                        left.type_name(),
-                        right.type_name()
-                    ));
+/// The size of the stack the program runs on. The interpreter keeps calls on
+/// the heap, but a compiled program nests each call on the native stack, so
+/// the main thread's would only allow shallow recursion with `--compile`.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// The part of the stack that compiled programs may not fill with calls.
+const STACK_RESERVE: usize = 16 * 1024 * 1024;
+
+fn main() -> ExitCode {
+    let thread = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run);
+
+    match thread {
+        Ok(thread) => thread
+            .join()
+            .unwrap_or_else(|err| std::panic::resume_unwind(err)),
+        Err(err) => {
+            eprintln!("Could not start the interpreter: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut options = Options::default();
+
+    while let Some(flag) = args.next_if(|arg| arg.starts_with('-')) {
+        match flag.as_str() {
+            "-c" | "--compile" => options.compile = true,
+            "-O" | "--optimize" => options.optimize = true,
+            "--no-prelude" => options.no_prelude = true,
+            "--max-depth" => {
+                let depth = args.next().and_then(|arg| arg.parse().ok());
+
+                match depth {
+                    Some(depth) => options.max_depth = Some(depth),
+                    None => {
+                        return report_usage_err(
+                            "--max-depth must be followed by a number",
+                        )
+                    }
                 }
-                OperationError::InvalidUnary(op, expr) => {
-                    report_runtime_err(format!(
-                        "Cannot use unary operator {:?} on type {}",
-                        op,
-                        expr.type_name(),
-                    ));
+            }
+            "--fuel" => {
+                let fuel = args.next().and_then(|arg| arg.parse().ok());
+
+                match fuel {
+                    Some(fuel) => options.fuel = Some(fuel),
+                    None => {
+                        return report_usage_err(
+                            "--fuel must be followed by a number",
+                        )
+                    }
                 }
-            },
-            RuntimeError::InvalidArgCount(exp, rec) => report_runtime_err(
-                format!("Expected {} args, got {}", exp, rec),
-            ),
-            RuntimeError::UndefinedIdentifier(name) => {
-                report_runtime_err(format!("Unknown identifier: {}", name))
             }
-            RuntimeError::InvalidArgumentType(expected, got) => {
-                report_runtime_err(format!(
-                    "Expected type {expected}, got {got}"
-                ))
+            "--max-memory" => {
+                let bytes = args.next().and_then(|arg| arg.parse().ok());
+
+                match bytes {
+                    Some(bytes) => options.max_memory = Some(bytes),
+                    None => {
+                        return report_usage_err(
+                            "--max-memory must be followed by a number",
+                        )
+                    }
+                }
             }
-            RuntimeError::NoScope => {
-                report_runtime_err("All scopes have been popped")
+            _ => return report_usage_err(format!("Unknown option: {}", flag)),
+        }
+    }
+
+    let Some(path) = args.next() else {
+        if options.compile || options.optimize {
+            return report_usage_err(
+                "--compile and --optimize need a path to the program",
+            );
+        }
+
+        let new_interpreter = || {
+            let mut interpreter = new_interpreter(&options);
+
+            if !options.no_prelude {
+                interpreter.load(prelude::decls());
             }
-            RuntimeError::IndexOutOfBounds(length, tried) => {
-                report_runtime_err(format!(
-                    "Index out of bounds: used index {} on a list of length {}",
-                    tried, length
-                ))
+
+            interpreter
+        };
+
+        return match Repl::new(new_interpreter).run() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Could not read the input: {}", err);
+                ExitCode::FAILURE
             }
-        },
+        };
+    };
+
+    let file = format!("./{}", path);
+
+    let source = match fs::read_to_string(&file) {
+        Ok(source) => source,
+        Err(err) => {
+            return report_usage_err(format!(
+                "Could not read {}: {}",
+                path, err
+            ))
+        }
+    };
+
+    let args = args.collect::<Vec<_>>();
+
+    let lexer = Lexer::new(source);
+
+    let tokens = match lexer.scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(parse_err) => return report_parse_err(parse_err.to_string()),
+    };
+
+    let parser = Parser::new(tokens);
+
+    let decls = match parser.parse() {
+        Ok(decls) => decls,
+        Err(parse_err) => return report_parse_err(parse_err.to_string()),
+    };
+
+    let decls = match ModuleLoader::new().load(Path::new(&file), decls) {
+        Ok(decls) => decls,
+        Err(module_err) => return report_module_err(module_err.to_string()),
+    };
+
+    let decls = if options.no_prelude {
+        decls
+    } else {
+        prelude::decls().into_iter().chain(decls).collect()
+    };
+
+    let decls = if options.optimize {
+        Optimizer::new(&decls).optimize(decls)
+    } else {
+        decls
+    };
+
+    let mut interpreter = new_interpreter(&options);
+
+    let result = if options.compile {
+        interpreter.interpret_compiled(decls, args)
+    } else {
+        interpreter.interpret(decls, args)
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(run_err) => report_runtime_err(run_err.to_string()),
+    }
+}
+
+/// Creates an interpreter with the limits given on the command line.
+fn new_interpreter(options: &Options) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+
+    if let Some(max_depth) = options.max_depth {
+        interpreter.set_max_depth(max_depth);
     }
+
+    // The rest of the stack is left for the builtins and for the calls made
+    // past the last check.
+    interpreter.set_stack_size(STACK_SIZE - STACK_RESERVE);
+
+    if let Some(fuel) = options.fuel {
+        interpreter.set_fuel(fuel);
+    }
+
+    if let Some(max_memory) = options.max_memory {
+        interpreter.set_max_memory(max_memory);
+    }
+
+    interpreter
 }
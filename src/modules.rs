@@ -0,0 +1,899 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::lexer::*;
+use crate::parser::*;
+use crate::syntax::*;
+
+/// An error raised while loading the modules a program imports.
+#[derive(Debug)]
+pub enum ModuleError {
+    /// The path of a module that could not be read.
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, ParseError),
+    /// The paths of modules that import each other, starting and ending with
+    /// the same one.
+    Cycle(Vec<PathBuf>),
+    /// The alias of a module that the file at the path does not import.
+    UnknownModule(String, PathBuf, Span),
+    /// A qualified name that its module does not declare.
+    UndefinedMember(String, PathBuf, Span),
+    /// A qualified name that its module does not mark `pub`.
+    PrivateMember(String, PathBuf, Span),
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(path, err) => {
+                write!(f, "Could not read {}: {}", path.display(), err)
+            }
+            Self::Parse(path, err) => {
+                write!(f, "Could not parse {}: {}", path.display(), err)
+            }
+            Self::Cycle(paths) => {
+                let paths = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+
+                write!(f, "Modules import each other: {}", paths.join(" -> "))
+            }
+            Self::UnknownModule(alias, path, span) => write!(
+                f,
+                "No module named {} is imported, at {} in {}",
+                alias,
+                span,
+                path.display()
+            ),
+            Self::UndefinedMember(name, path, span) => write!(
+                f,
+                "{} is not declared by its module, at {} in {}",
+                name,
+                span,
+                path.display()
+            ),
+            Self::PrivateMember(name, path, span) => write!(
+                f,
+                "{} is not marked pub in its module, at {} in {}",
+                name,
+                span,
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for ModuleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(_, err) => Some(err),
+            Self::Parse(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// What a module declares at its top level.
+struct Module {
+    /// What the names of its functions and globals are prefixed with once it
+    /// is loaded, which keeps them apart from those of other modules. The
+    /// program itself has none.
+    prefix: Option<String>,
+    /// Its functions, and whether each is public.
+    fns: HashMap<String, bool>,
+    /// Its globals, and whether each is public.
+    globals: HashMap<String, bool>,
+    /// The canonical paths of the modules it imports, by their alias.
+    imports: HashMap<String, PathBuf>,
+}
+
+impl Module {
+    /// Returns the name that the function or global is known by once the
+    /// module is loaded.
+    fn qualify(&self, name: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}.{}", prefix, name),
+            None => name.to_owned(),
+        }
+    }
+}
+
+/// A module that is being loaded, and so cannot be imported again yet.
+struct Loading {
+    canonical: PathBuf,
+    /// The path as it was imported, for reporting cycles.
+    path: PathBuf,
+    prefix: String,
+}
+
+/// Loads the modules a program imports and links them into it.
+///
+/// The functions and globals of each module are renamed with a prefix, so
+/// that they cannot clash with those of other modules, and qualified names
+/// such as `util.helper` are resolved to them. Each module is loaded only
+/// once, however many times it is imported.
+#[derive(Default)]
+pub struct ModuleLoader {
+    /// The modules loaded so far, by their canonical path.
+    modules: HashMap<PathBuf, Module>,
+    /// The modules being loaded, outermost first.
+    loading: Vec<Loading>,
+    /// The modules imported by the programs loaded so far, by their alias,
+    /// which later programs can use too, as each input to the REPL does.
+    imports: HashMap<String, PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the modules that the program read from the path imports, which
+    /// are found relative to its directory. Returns the declarations of the
+    /// modules that had not been loaded yet, in the order they were imported,
+    /// followed by the program's own.
+    pub fn load(
+        &mut self,
+        path: &Path,
+        decls: Vec<Decl>,
+    ) -> Result<Vec<Decl>, ModuleError> {
+        let loaded = self.modules.keys().cloned().collect::<HashSet<_>>();
+        let mut linked = vec![];
+
+        let imports = self.imports.clone();
+
+        match self.link(path, decls, None, imports, &mut linked) {
+            Ok(program) => {
+                self.imports = program.imports;
+                Ok(linked)
+            }
+            Err(err) => {
+                // The declarations of modules loaded along the way are
+                // dropped with the error, so they must be loaded again by the
+                // next program.
+                self.modules.retain(|path, _| loaded.contains(path));
+                self.loading.clear();
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Loads the imports of the declarations, adding them to those already
+    /// imported, then renames the declarations and adds them to `linked`.
+    /// Returns what they declare.
+    fn link(
+        &mut self,
+        path: &Path,
+        decls: Vec<Decl>,
+        prefix: Option<String>,
+        mut imports: HashMap<String, PathBuf>,
+        linked: &mut Vec<Decl>,
+    ) -> Result<Module, ModuleError> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        for decl in decls.iter() {
+            if let Decl::Import(import) = decl {
+                let canonical = self.import(dir.join(&import.path), linked)?;
+                imports.insert(import.alias.clone(), canonical);
+            }
+        }
+
+        let mut module = Module {
+            prefix,
+            fns: HashMap::new(),
+            globals: HashMap::new(),
+            imports,
+        };
+
+        for decl in decls.iter() {
+            match decl {
+                Decl::FnDecl(fn_decl) => {
+                    module.fns.insert(fn_decl.name.clone(), fn_decl.public);
+                }
+                Decl::Stmt(Stmt::Global(global)) => {
+                    module.globals.insert(global.var.clone(), global.public);
+                }
+                _ => {}
+            }
+        }
+
+        let imports = module
+            .imports
+            .iter()
+            .map(|(alias, canonical)| (alias.clone(), &self.modules[canonical]))
+            .collect::<HashMap<_, _>>();
+
+        let mut renamer = Renamer {
+            path,
+            module: &module,
+            fn_names: Rc::new(fn_names(&module, &imports)),
+            imports,
+            locals: vec![],
+        };
+
+        for decl in decls {
+            if let Some(decl) = renamer.rename_decl(decl)? {
+                linked.push(decl);
+            }
+        }
+
+        Ok(module)
+    }
+
+    /// Loads the module at the path unless it already has been, returning
+    /// its canonical path.
+    fn import(
+        &mut self,
+        path: PathBuf,
+        linked: &mut Vec<Decl>,
+    ) -> Result<PathBuf, ModuleError> {
+        let canonical = fs::canonicalize(&path)
+            .map_err(|err| ModuleError::Read(path.clone(), err))?;
+
+        if let Some(start) = self
+            .loading
+            .iter()
+            .position(|loading| loading.canonical == canonical)
+        {
+            let mut cycle = self.loading[start..]
+                .iter()
+                .map(|loading| loading.path.clone())
+                .collect::<Vec<_>>();
+            cycle.push(path);
+
+            return Err(ModuleError::Cycle(cycle));
+        }
+
+        if self.modules.contains_key(&canonical) {
+            return Ok(canonical);
+        }
+
+        let source = fs::read_to_string(&path)
+            .map_err(|err| ModuleError::Read(path.clone(), err))?;
+        let tokens = Lexer::new(source)
+            .scan_tokens()
+            .map_err(|err| ModuleError::Parse(path.clone(), err))?;
+        let decls = Parser::new(tokens)
+            .parse()
+            .map_err(|err| ModuleError::Parse(path.clone(), err))?;
+
+        let prefix = self.unique_prefix(&path);
+
+        self.loading.push(Loading {
+            canonical: canonical.clone(),
+            path: path.clone(),
+            prefix: prefix.clone(),
+        });
+
+        let module =
+            self.link(&path, decls, Some(prefix), HashMap::new(), linked)?;
+
+        self.loading.pop();
+        self.modules.insert(canonical.clone(), module);
+
+        Ok(canonical)
+    }
+
+    /// Returns the prefix for the module at the path, which is its file name
+    /// without the extension, numbered if another module already has it.
+    fn unique_prefix(&self, path: &Path) -> String {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let taken = |prefix: &str| {
+            self.modules
+                .values()
+                .any(|module| module.prefix.as_deref() == Some(prefix))
+                || self.loading.iter().any(|loading| loading.prefix == prefix)
+        };
+
+        let mut prefix = stem.clone();
+        let mut number = 1;
+
+        while taken(&prefix) {
+            number += 1;
+            prefix = format!("{}{}", stem, number);
+        }
+
+        prefix
+    }
+}
+
+/// Returns the functions the module can call by name, which are its own and
+/// the public ones of the modules it imports, qualified by their alias.
+fn fn_names(module: &Module, imports: &HashMap<String, &Module>) -> FnNames {
+    let own = module
+        .fns
+        .keys()
+        .map(|name| (name.clone(), module.qualify(name)));
+
+    let imported =
+        imports.iter().flat_map(|(alias, import)| {
+            import.fns.iter().filter(|(_, &public)| public).map(
+                move |(name, _)| {
+                    (format!("{}.{}", alias, name), import.qualify(name))
+                },
+            )
+        });
+
+    FnNames::new(own.chain(imported).collect())
+}
+
+/// Renames the functions and globals a module uses to the names they are
+/// known by once it is loaded.
+struct Renamer<'a> {
+    /// The path of the module, for reporting errors.
+    path: &'a Path,
+    module: &'a Module,
+    /// The functions it can call by name, which every call it makes is given
+    /// for the builtins among them that call a function by name.
+    fn_names: Rc<FnNames>,
+    /// The modules it imports, by their alias.
+    imports: HashMap<String, &'a Module>,
+    /// The local variables declared in each enclosing scope, which hide the
+    /// module's globals.
+    locals: Vec<HashSet<String>>,
+}
+
+impl Renamer<'_> {
+    /// Returns the renamed declaration, or `None` if it is an import.
+    fn rename_decl(&mut self, decl: Decl) -> Result<Option<Decl>, ModuleError> {
+        let decl = match decl {
+            Decl::FnDecl(fn_decl) => {
+                self.locals.push(fn_decl.params.iter().cloned().collect());
+                let body = self.rename_body(fn_decl.body);
+                self.locals.pop();
+
+                Decl::FnDecl(FnDecl {
+                    name: self.module.qualify(&fn_decl.name),
+                    params: fn_decl.params,
+                    body: body?,
+                    public: fn_decl.public,
+                })
+            }
+            Decl::Stmt(stmt) => Decl::Stmt(self.rename_stmt(stmt)?),
+            Decl::Import(_) => return Ok(None),
+        };
+
+        Ok(Some(decl))
+    }
+
+    fn rename_body(
+        &mut self,
+        body: Vec<Stmt>,
+    ) -> Result<Vec<Stmt>, ModuleError> {
+        self.locals.push(HashSet::new());
+
+        let body = body
+            .into_iter()
+            .map(|stmt| self.rename_stmt(stmt))
+            .collect();
+
+        self.locals.pop();
+
+        body
+    }
+
+    fn rename_stmt(&mut self, stmt: Stmt) -> Result<Stmt, ModuleError> {
+        let stmt = match stmt {
+            Stmt::FnCall(fn_call) => {
+                Stmt::FnCall(self.rename_fn_call(fn_call)?)
+            }
+            Stmt::Return(ReturnStmt { expr }) => Stmt::Return(ReturnStmt {
+                expr: self.rename_expr(expr)?,
+            }),
+            Stmt::If(IfStmt { cond, body, span }) => Stmt::If(IfStmt {
+                cond: self.rename_expr(cond)?,
+                body: self.rename_body(body)?,
+                span,
+            }),
+            Stmt::While(WhileStmt { cond, body, span }) => {
+                Stmt::While(WhileStmt {
+                    cond: self.rename_expr(cond)?,
+                    body: self.rename_body(body)?,
+                    span,
+                })
+            }
+            Stmt::Assign(AssignStmt { var, val, span }) => {
+                Stmt::Assign(AssignStmt {
+                    val: self.rename_expr(val)?,
+                    var: self.rename_var(var, span)?,
+                    span,
+                })
+            }
+            Stmt::Decl(DeclStmt { var, val }) => {
+                // The value is renamed first so that `let x = x;` refers to
+                // the outer variable.
+                let val = self.rename_expr(val)?;
+
+                if let Some(scope) = self.locals.last_mut() {
+                    scope.insert(var.clone());
+                }
+
+                Stmt::Decl(DeclStmt { var, val })
+            }
+            Stmt::Global(GlobalStmt {
+                var,
+                val,
+                constant,
+                public,
+            }) => Stmt::Global(GlobalStmt {
+                val: self.rename_expr(val)?,
+                var: self.module.qualify(&var),
+                constant,
+                public,
+            }),
+            Stmt::Throw(ThrowStmt { expr, span }) => Stmt::Throw(ThrowStmt {
+                expr: self.rename_expr(expr)?,
+                span,
+            }),
+            Stmt::Try(TryStmt {
+                body,
+                catch,
+                finally,
+            }) => {
+                let body = self.rename_body(body)?;
+
+                let catch = match catch {
+                    Some(CatchClause { var, body }) => {
+                        self.locals.push(HashSet::from([var.clone()]));
+                        let body = self.rename_body(body);
+                        self.locals.pop();
+
+                        Some(CatchClause { var, body: body? })
+                    }
+                    None => None,
+                };
+
+                let finally = match finally {
+                    Some(body) => Some(self.rename_body(body)?),
+                    None => None,
+                };
+
+                Stmt::Try(TryStmt {
+                    body,
+                    catch,
+                    finally,
+                })
+            }
+        };
+
+        Ok(stmt)
+    }
+
+    fn rename_fn_call(
+        &mut self,
+        fn_call: FnCall,
+    ) -> Result<FnCall, ModuleError> {
+        let FnCall {
+            name, args, span, ..
+        } = fn_call;
+
+        let args = args
+            .into_iter()
+            .map(|arg| self.rename_expr(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(FnCall {
+            name: self.rename_fn_name(name, span)?,
+            args,
+            span,
+            fn_names: Some(Rc::clone(&self.fn_names)),
+        })
+    }
+
+    /// Returns the name the called function is known by. Functions the module
+    /// does not declare are builtins, whose names are left as they are.
+    fn rename_fn_name(
+        &self,
+        name: String,
+        span: Span,
+    ) -> Result<String, ModuleError> {
+        match name.split_once('.') {
+            Some((alias, member)) => {
+                self.resolve(alias, member, span, |module| &module.fns)
+            }
+            None if self.module.fns.contains_key(&name) => {
+                Ok(self.module.qualify(&name))
+            }
+            None => Ok(name),
+        }
+    }
+
+    fn rename_expr(&mut self, expr: Expr) -> Result<Expr, ModuleError> {
+        let expr = match expr {
+            Expr::Identfier(name, span) => {
+                Expr::Identfier(self.rename_var(name, span)?, span)
+            }
+            Expr::FnCall(fn_call) => {
+                Expr::FnCall(self.rename_fn_call(fn_call)?)
+            }
+            Expr::FieldAccess(FieldAccess { obj, field, span }) => match *obj {
+                // A global of an imported module is read like a field of the
+                // module, unless a local variable hides it.
+                Expr::Identfier(alias, alias_span)
+                    if self.imports.contains_key(&alias)
+                        && !self.is_local(&alias) =>
+                {
+                    let name =
+                        self.resolve(&alias, &field, alias_span, |module| {
+                            &module.globals
+                        })?;
+
+                    Expr::Identfier(name, alias_span)
+                }
+                obj => Expr::FieldAccess(FieldAccess {
+                    obj: Box::new(self.rename_expr(obj)?),
+                    field,
+                    span,
+                }),
+            },
+            Expr::Binary(Binary {
+                op,
+                left,
+                right,
+                span,
+            }) => Expr::Binary(Binary {
+                op,
+                left: Box::new(self.rename_expr(*left)?),
+                right: Box::new(self.rename_expr(*right)?),
+                span,
+            }),
+            Expr::Unary(Unary { op, expr, span }) => Expr::Unary(Unary {
+                op,
+                expr: Box::new(self.rename_expr(*expr)?),
+                span,
+            }),
+            Expr::ObjectLiteral(fields) => Expr::ObjectLiteral(
+                fields
+                    .into_iter()
+                    .map(|(name, expr)| Ok((name, self.rename_expr(expr)?)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::ListLiteral(values) => Expr::ListLiteral(
+                values
+                    .into_iter()
+                    .map(|expr| self.rename_expr(expr))
+                    .collect::<Result<_, _>>()?,
+            ),
+            expr => expr,
+        };
+
+        Ok(expr)
+    }
+
+    /// Returns the name the variable is known by, which is left as it is
+    /// unless it is a global of this module or another.
+    fn rename_var(
+        &self,
+        name: String,
+        span: Span,
+    ) -> Result<String, ModuleError> {
+        match name.split_once('.') {
+            // Other qualified names are left for the program to report, as
+            // they may be meant as fields of a variable.
+            Some((alias, member))
+                if self.imports.contains_key(alias)
+                    && !self.is_local(alias) =>
+            {
+                self.resolve(alias, member, span, |module| &module.globals)
+            }
+            Some(_) => Ok(name),
+            None if self.is_local(&name) => Ok(name),
+            None if self.module.globals.contains_key(&name) => {
+                Ok(self.module.qualify(&name))
+            }
+            None => Ok(name),
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|scope| scope.contains(name))
+    }
+
+    /// Returns the name the member of an imported module is known by, given
+    /// the kind of members to find it among, as long as it is public.
+    fn resolve(
+        &self,
+        alias: &str,
+        member: &str,
+        span: Span,
+        members: fn(&Module) -> &HashMap<String, bool>,
+    ) -> Result<String, ModuleError> {
+        let name = || format!("{}.{}", alias, member);
+        let path = || self.path.to_owned();
+
+        let module = self.imports.get(alias).ok_or_else(|| {
+            ModuleError::UnknownModule(alias.to_owned(), path(), span)
+        })?;
+
+        match members(module).get(member) {
+            Some(true) => Ok(module.qualify(member)),
+            Some(false) => {
+                Err(ModuleError::PrivateMember(name(), path(), span))
+            }
+            None => Err(ModuleError::UndefinedMember(name(), path(), span)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the files into a new directory for the test, and loads the
+    /// program in its `main.rl`.
+    fn load(
+        test: &str,
+        files: &[(&str, &str)],
+    ) -> Result<Vec<Decl>, ModuleError> {
+        let dir = std::env::temp_dir().join(format!(
+            "rlang-modules-{}-{}",
+            std::process::id(),
+            test
+        ));
+
+        for (name, source) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+
+        let path = dir.join("main.rl");
+        let source = fs::read_to_string(&path).unwrap();
+        let tokens = Lexer::new(source).scan_tokens().unwrap();
+        let decls = Parser::new(tokens).parse().unwrap();
+
+        let result = ModuleLoader::new().load(&path, decls);
+        fs::remove_dir_all(&dir).unwrap();
+
+        result
+    }
+
+    fn decl_names(decls: &[Decl]) -> Vec<&str> {
+        decls
+            .iter()
+            .filter_map(|decl| match decl {
+                Decl::FnDecl(fn_decl) => Some(fn_decl.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the body of the function with the given name.
+    fn body<'a>(decls: &'a [Decl], name: &str) -> &'a [Stmt] {
+        decls
+            .iter()
+            .find_map(|decl| match decl {
+                Decl::FnDecl(fn_decl) if fn_decl.name == name => {
+                    Some(fn_decl.body.as_slice())
+                }
+                _ => None,
+            })
+            .expect("no function with the name")
+    }
+
+    /// Returns the name of the call the statement makes, and its arguments.
+    fn call(stmt: &Stmt) -> (&str, &[Expr]) {
+        match stmt {
+            Stmt::FnCall(FnCall { name, args, .. })
+            | Stmt::Return(ReturnStmt {
+                expr: Expr::FnCall(FnCall { name, args, .. }),
+            }) => (name, args),
+            _ => panic!("the statement is not a call"),
+        }
+    }
+
+    /// Returns the functions that the call the statement makes can name.
+    fn call_names(stmt: &Stmt) -> &FnNames {
+        match stmt {
+            Stmt::FnCall(FnCall { fn_names, .. })
+            | Stmt::Return(ReturnStmt {
+                expr: Expr::FnCall(FnCall { fn_names, .. }),
+            }) => fn_names.as_deref().expect("the call has no names"),
+            _ => panic!("the statement is not a call"),
+        }
+    }
+
+    const UTIL: &str = r#"
+        pub const SCALE = 10;
+        let count = 0;
+
+        fn helper(x) {
+            count = count + 1;
+            return x;
+        }
+
+        pub fn scale(x) {
+            return helper(x) * SCALE;
+        }
+
+        pub fn scale_all(xs) {
+            return map(xs, "scale");
+        }
+    "#;
+
+    #[test]
+    fn qualifies_names_with_the_module() {
+        let decls = load(
+            "qualify",
+            &[
+                ("util.rl", UTIL),
+                (
+                    "main.rl",
+                    r#"
+                    import "util.rl" as util;
+
+                    fn main() {
+                        return util.scale(util.SCALE);
+                    }
+                    "#,
+                ),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            decl_names(&decls),
+            ["util.helper", "util.scale", "util.scale_all", "main"]
+        );
+
+        let (name, args) = call(&body(&decls, "main")[0]);
+        assert_eq!(name, "util.scale");
+        assert!(
+            matches!(&args[0], Expr::Identfier(var, _) if var == "util.SCALE")
+        );
+
+        let Stmt::Assign(assign) = &body(&decls, "util.helper")[0] else {
+            panic!("not an assignment");
+        };
+        assert_eq!(assign.var, "util.count");
+
+        let (name, _) = call(&body(&decls, "util.scale_all")[0]);
+        assert_eq!(name, "map");
+    }
+
+    #[test]
+    fn gives_calls_the_functions_they_can_name() {
+        let decls = load(
+            "callbacks",
+            &[
+                ("lib/util.rl", UTIL),
+                ("util.rl", "pub fn inc(x) { return x + 1; }"),
+                (
+                    "main.rl",
+                    r#"
+                    import "util.rl" as first;
+                    import "lib/util.rl" as util;
+
+                    fn main(xs) {
+                        return sort_by(map(xs, "util.scale"), "compare");
+                    }
+                    "#,
+                ),
+            ],
+        )
+        .unwrap();
+
+        // The second module named util is numbered to keep them apart.
+        let names = call_names(&body(&decls, "util2.scale_all")[0]);
+        assert_eq!(names.resolve("scale"), Some("util2.scale"));
+        assert_eq!(names.resolve("inc"), Some("inc"));
+        assert_eq!(names.resolve("first.inc"), None);
+
+        let names = call_names(&body(&decls, "main")[0]);
+        assert_eq!(names.resolve("util.scale"), Some("util2.scale"));
+        assert_eq!(names.resolve("first.inc"), Some("util.inc"));
+        assert_eq!(names.resolve("compare"), Some("compare"));
+        assert_eq!(names.resolve("util.helper"), None);
+        assert_eq!(names.resolve("other.scale"), None);
+    }
+
+    #[test]
+    fn rejects_private_members() {
+        let files = |main| [("util.rl", UTIL), ("main.rl", main)];
+
+        let err = load(
+            "private_fn",
+            &files(r#"import "util.rl" as util; util.helper(1);"#),
+        );
+        assert!(matches!(err, Err(ModuleError::PrivateMember(name, ..))
+            if name == "util.helper"));
+
+        let err = load(
+            "private_global",
+            &files(r#"import "util.rl" as util; print(util.count);"#),
+        );
+        assert!(matches!(err, Err(ModuleError::PrivateMember(name, ..))
+            if name == "util.count"));
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        let files = |main| [("util.rl", UTIL), ("main.rl", main)];
+
+        let err = load(
+            "undefined",
+            &files(r#"import "util.rl" as util; util.missing();"#),
+        );
+        assert!(matches!(err, Err(ModuleError::UndefinedMember(..))));
+
+        let err = load("unknown", &files("other.scale(1);"));
+        assert!(matches!(err, Err(ModuleError::UnknownModule(alias, ..))
+            if alias == "other"));
+    }
+
+    #[test]
+    fn leaves_hidden_globals_alone() {
+        let decls = load(
+            "locals",
+            &[
+                ("util.rl", UTIL),
+                (
+                    "main.rl",
+                    r#"
+                    import "util.rl" as util;
+
+                    fn main(util) {
+                        return print(util.SCALE);
+                    }
+                    "#,
+                ),
+            ],
+        )
+        .unwrap();
+
+        let (_, args) = call(&body(&decls, "main")[0]);
+        assert!(matches!(&args[0], Expr::FieldAccess(_)));
+    }
+
+    #[test]
+    fn leaves_fields_of_variables_alone() {
+        let decls = load(
+            "fields",
+            &[(
+                "main.rl",
+                "try { throw 1; } catch (e) { e.x = 2; } other.x = 3;",
+            )],
+        )
+        .unwrap();
+
+        let Decl::Stmt(Stmt::Try(TryStmt {
+            catch: Some(catch), ..
+        })) = &decls[0]
+        else {
+            panic!("not a try statement");
+        };
+        assert!(matches!(&catch.body[0], Stmt::Assign(assign)
+            if assign.var == "e.x"));
+
+        assert!(matches!(&decls[1], Decl::Stmt(Stmt::Assign(assign))
+            if assign.var == "other.x"));
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let err = load(
+            "cycle",
+            &[
+                ("a.rl", r#"import "b.rl" as b;"#),
+                ("b.rl", r#"import "a.rl" as a;"#),
+                ("main.rl", r#"import "a.rl" as a;"#),
+            ],
+        );
+
+        assert!(matches!(err, Err(ModuleError::Cycle(paths))
+            if paths.len() == 3));
+    }
+}
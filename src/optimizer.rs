@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::syntax::*;
+use crate::value::*;
+
+/// Simplifies the syntax tree before it is run: constant expressions are
+/// folded, unreachable statements are removed and constant variables are
+/// substituted into the expressions that use them.
+pub struct Optimizer {
+    /// Every variable name that is assigned to anywhere in the program. A
+    /// called function can assign to its caller's variables, so these are
+    /// never treated as constants.
+    assigned: HashSet<String>,
+
+    /// The constant values of the variables in each enclosing scope, or
+    /// `None` if the variable is not a constant.
+    scopes: Vec<HashMap<String, Option<Expr>>>,
+
+    /// The constants declared by top-level statements, which are only
+    /// substituted into other top-level statements.
+    top_level: HashMap<String, Option<Expr>>,
+}
+
+impl Optimizer {
+    pub fn new(decls: &[Decl]) -> Self {
+        let mut assigned = HashSet::new();
+
+        for decl in decls {
+            match decl {
+                Decl::FnDecl(fn_decl) => {
+                    collect_assigned(&fn_decl.body, &mut assigned)
+                }
+                Decl::Stmt(stmt) => {
+                    collect_assigned(std::slice::from_ref(stmt), &mut assigned)
+                }
+                Decl::Import(_) => {}
+            }
+        }
+
+        Self {
+            assigned,
+            scopes: vec![],
+            top_level: HashMap::new(),
+        }
+    }
+
+    pub fn optimize(mut self, decls: Vec<Decl>) -> Vec<Decl> {
+        decls
+            .into_iter()
+            .filter_map(|decl| match decl {
+                Decl::FnDecl(fn_decl) => {
+                    Some(Decl::FnDecl(self.optimize_fn(fn_decl)))
+                }
+                Decl::Stmt(stmt) => {
+                    self.optimize_top_level(stmt).map(Decl::Stmt)
+                }
+                Decl::Import(import) => Some(Decl::Import(import)),
+            })
+            .collect()
+    }
+
+    fn optimize_top_level(&mut self, stmt: Stmt) -> Option<Stmt> {
+        self.scopes.push(std::mem::take(&mut self.top_level));
+        let stmt = self.optimize_stmt(stmt);
+        self.top_level = self.scopes.pop().expect("top-level scope was pushed");
+
+        stmt
+    }
+
+    fn optimize_fn(&mut self, fn_decl: FnDecl) -> FnDecl {
+        let params = fn_decl
+            .params
+            .iter()
+            .map(|param| (param.clone(), None))
+            .collect();
+
+        self.scopes.push(params);
+        let body = self.optimize_body(fn_decl.body);
+        self.scopes.pop();
+
+        FnDecl {
+            name: fn_decl.name,
+            params: fn_decl.params,
+            body,
+            public: fn_decl.public,
+        }
+    }
+
+    fn optimize_body(&mut self, body: Vec<Stmt>) -> Vec<Stmt> {
+        self.scopes.push(HashMap::new());
+
+        let mut stmts = vec![];
+
+        for stmt in body {
+            let Some(stmt) = self.optimize_stmt(stmt) else {
+                continue;
+            };
+
+            let returns = matches!(stmt, Stmt::Return(_) | Stmt::Throw(_));
+
+            stmts.push(stmt);
+
+            if returns {
+                break;
+            }
+        }
+
+        self.scopes.pop();
+
+        stmts
+    }
+
+    /// Returns the optimized statement, or `None` if it can never have an
+    /// effect.
+    fn optimize_stmt(&mut self, stmt: Stmt) -> Option<Stmt> {
+        let stmt = match stmt {
+            Stmt::FnCall(fn_call) => {
+                Stmt::FnCall(self.optimize_fn_call(fn_call))
+            }
+            Stmt::Return(ReturnStmt { expr }) => Stmt::Return(ReturnStmt {
+                expr: self.optimize_expr(expr),
+            }),
+            Stmt::If(IfStmt { cond, body, span }) => {
+                let cond = self.optimize_expr(cond);
+
+                if let Expr::BooleanLiteral(false) = cond {
+                    return None;
+                }
+
+                Stmt::If(IfStmt {
+                    cond,
+                    body: self.optimize_body(body),
+                    span,
+                })
+            }
+            Stmt::While(WhileStmt { cond, body, span }) => {
+                let cond = self.optimize_expr(cond);
+
+                if let Expr::BooleanLiteral(false) = cond {
+                    return None;
+                }
+
+                Stmt::While(WhileStmt {
+                    cond,
+                    body: self.optimize_body(body),
+                    span,
+                })
+            }
+            Stmt::Assign(AssignStmt { var, val, span }) => {
+                Stmt::Assign(AssignStmt {
+                    var,
+                    val: self.optimize_expr(val),
+                    span,
+                })
+            }
+            Stmt::Decl(DeclStmt { var, val }) => {
+                let val = self.optimize_expr(val);
+
+                // The declaration itself is kept, since functions called from
+                // here are still able to read the variable.
+                let constant = match self.assigned.contains(&var) {
+                    true => None,
+                    false => literal_value(&val).and_then(value_literal),
+                };
+
+                self.scopes
+                    .last_mut()
+                    .expect("no scope to declare in")
+                    .insert(var.clone(), constant);
+
+                Stmt::Decl(DeclStmt { var, val })
+            }
+            Stmt::Global(GlobalStmt {
+                var,
+                val,
+                constant,
+                public,
+            }) => {
+                let val = self.optimize_expr(val);
+
+                // Assigning to a `const` fails, so it keeps its value even if
+                // the program tries.
+                let value = match constant || !self.assigned.contains(&var) {
+                    true => literal_value(&val).and_then(value_literal),
+                    false => None,
+                };
+
+                self.scopes
+                    .last_mut()
+                    .expect("no scope to declare in")
+                    .insert(var.clone(), value);
+
+                Stmt::Global(GlobalStmt {
+                    var,
+                    val,
+                    constant,
+                    public,
+                })
+            }
+            Stmt::Throw(ThrowStmt { expr, span }) => Stmt::Throw(ThrowStmt {
+                expr: self.optimize_expr(expr),
+                span,
+            }),
+            Stmt::Try(TryStmt {
+                body,
+                catch,
+                finally,
+            }) => {
+                let body = self.optimize_body(body);
+
+                let catch = catch.map(|CatchClause { var, body }| {
+                    self.scopes.push(HashMap::from([(var.clone(), None)]));
+                    let body = self.optimize_body(body);
+                    self.scopes.pop();
+
+                    CatchClause { var, body }
+                });
+
+                let finally = finally.map(|body| self.optimize_body(body));
+
+                Stmt::Try(TryStmt {
+                    body,
+                    catch,
+                    finally,
+                })
+            }
+        };
+
+        Some(stmt)
+    }
+
+    fn optimize_fn_call(&mut self, fn_call: FnCall) -> FnCall {
+        FnCall {
+            name: fn_call.name,
+            args: fn_call
+                .args
+                .into_iter()
+                .map(|arg| self.optimize_expr(arg))
+                .collect(),
+            span: fn_call.span,
+            fn_names: fn_call.fn_names,
+        }
+    }
+
+    fn optimize_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Identfier(name, span) => {
+                let constant =
+                    self.scopes.iter().rev().find_map(|scope| scope.get(&name));
+
+                match constant {
+                    Some(Some(literal)) => copy_literal(literal),
+                    _ => Expr::Identfier(name, span),
+                }
+            }
+            Expr::FnCall(fn_call) => {
+                Expr::FnCall(self.optimize_fn_call(fn_call))
+            }
+            Expr::Binary(Binary {
+                op,
+                left,
+                right,
+                span,
+            }) => {
+                let left = self.optimize_expr(*left);
+                let right = self.optimize_expr(*right);
+
+                if let (Some(left), Some(right)) =
+                    (literal_value(&left), literal_value(&right))
+                {
+                    if let Some(folded) = fold_binary(&left, op, &right) {
+                        return folded;
+                    }
+                }
+
+                Expr::Binary(Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    span,
+                })
+            }
+            Expr::Unary(Unary { op, expr, span }) => {
+                let expr = self.optimize_expr(*expr);
+
+                if let Some(value) = literal_value(&expr) {
+                    if let Some(folded) =
+                        value.operate_unary(op).ok().and_then(value_literal)
+                    {
+                        return folded;
+                    }
+                }
+
+                Expr::Unary(Unary {
+                    op,
+                    expr: Box::new(expr),
+                    span,
+                })
+            }
+            Expr::FieldAccess(FieldAccess { obj, field, span }) => {
+                Expr::FieldAccess(FieldAccess {
+                    obj: Box::new(self.optimize_expr(*obj)),
+                    field,
+                    span,
+                })
+            }
+            Expr::ObjectLiteral(fields) => Expr::ObjectLiteral(
+                fields
+                    .into_iter()
+                    .map(|(name, expr)| (name, self.optimize_expr(expr)))
+                    .collect(),
+            ),
+            Expr::ListLiteral(values) => Expr::ListLiteral(
+                values
+                    .into_iter()
+                    .map(|expr| self.optimize_expr(expr))
+                    .collect(),
+            ),
+            expr => expr,
+        }
+    }
+}
+
+/// Adds the name of every variable assigned to in the body to the set.
+fn collect_assigned(body: &[Stmt], assigned: &mut HashSet<String>) {
+    for stmt in body {
+        match stmt {
+            Stmt::Assign(AssignStmt { var, .. }) => {
+                assigned.insert(var.clone());
+            }
+            Stmt::If(IfStmt { body, .. })
+            | Stmt::While(WhileStmt { body, .. }) => {
+                collect_assigned(body, assigned)
+            }
+            Stmt::Try(TryStmt {
+                body,
+                catch,
+                finally,
+            }) => {
+                collect_assigned(body, assigned);
+
+                if let Some(catch) = catch {
+                    collect_assigned(&catch.body, assigned);
+                }
+
+                if let Some(finally) = finally {
+                    collect_assigned(finally, assigned);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Folds a binary operation on two constants, unless doing so would fail or
+/// overflow. Those are left for the program to report when it runs.
+fn fold_binary(left: &Value, op: Operator, right: &Value) -> Option<Expr> {
+    if let (Value::Number(num1), Value::Number(num2)) = (left, right) {
+        let fits = match op {
+            Operator::Plus => num1.checked_add(*num2).is_some(),
+            Operator::Minus => num1.checked_sub(*num2).is_some(),
+            Operator::Star => num1.checked_mul(*num2).is_some(),
+            Operator::Slash => num1.checked_div(*num2).is_some(),
+            _ => true,
+        };
+
+        if !fits {
+            return None;
+        }
+    }
+
+    left.operate(right, op).ok().and_then(value_literal)
+}
+
+/// Returns the value of a literal expression that can be folded.
+fn literal_value(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::NumberLiteral(num) => Some(Value::Number(*num)),
+        Expr::BooleanLiteral(bool) => Some(Value::Boolean(*bool)),
+        Expr::StringLiteral(str) => Some(Value::String(Rc::new(str.clone()))),
+        Expr::NullLiteral => Some(Value::Null),
+        _ => None,
+    }
+}
+
+/// Turns a value back into a literal expression, if it has one.
+fn value_literal(value: Value) -> Option<Expr> {
+    match value {
+        Value::Number(num) => Some(Expr::NumberLiteral(num)),
+        Value::Boolean(bool) => Some(Expr::BooleanLiteral(bool)),
+        Value::String(str) => {
+            Some(Expr::StringLiteral(Rc::unwrap_or_clone(str)))
+        }
+        Value::Null => Some(Expr::NullLiteral),
+        Value::Object(_) | Value::List(_) => None,
+    }
+}
+
+fn copy_literal(expr: &Expr) -> Expr {
+    literal_value(expr)
+        .and_then(value_literal)
+        .expect("not a literal expression")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Returns the optimized body of the first function in the source.
+    fn optimize_fn(source: &str) -> Vec<Stmt> {
+        let tokens = Lexer::new(source.to_owned()).scan_tokens().unwrap();
+        let decls = Parser::new(tokens).parse().unwrap();
+
+        Optimizer::new(&decls)
+            .optimize(decls)
+            .into_iter()
+            .find_map(|decl| match decl {
+                Decl::FnDecl(fn_decl) => Some(fn_decl.body),
+                _ => None,
+            })
+            .expect("no function in the source")
+    }
+
+    fn returned(body: &[Stmt]) -> &Expr {
+        match body.last() {
+            Some(Stmt::Return(ReturnStmt { expr })) => expr,
+            _ => panic!("the body does not end with a return"),
+        }
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        let body = optimize_fn("fn f() { return -2 + 3 * 4 == 10; }");
+
+        assert!(matches!(returned(&body), Expr::BooleanLiteral(true)));
+
+        let body = optimize_fn(r#"fn f() { return "a" + "b"; }"#);
+
+        assert!(matches!(returned(&body), Expr::StringLiteral(s) if s == "ab"));
+    }
+
+    #[test]
+    fn leaves_failing_operations_to_run() {
+        let body = optimize_fn("fn f() { return 2147483647 + 1; }");
+        assert!(matches!(returned(&body), Expr::Binary(_)));
+
+        let body = optimize_fn("fn f() { return 1 / 0; }");
+        assert!(matches!(returned(&body), Expr::Binary(_)));
+
+        let body = optimize_fn(r#"fn f() { return 1 < "a"; }"#);
+        assert!(matches!(returned(&body), Expr::Binary(_)));
+    }
+
+    #[test]
+    fn substitutes_constant_variables() {
+        let body = optimize_fn("fn f() { let x = 4; return x * 2; }");
+
+        // The declaration is kept for the functions it calls to read.
+        assert_eq!(body.len(), 2);
+        assert!(matches!(returned(&body), Expr::NumberLiteral(8)));
+    }
+
+    #[test]
+    fn keeps_variables_assigned_anywhere() {
+        let body = optimize_fn(
+            "fn f() { let x = 4; g(); return x; } fn g() { x = 5; }",
+        );
+
+        assert!(
+            matches!(returned(&body), Expr::Identfier(name, _) if name == "x")
+        );
+    }
+
+    #[test]
+    fn keeps_parameters() {
+        let body = optimize_fn("fn f(x) { return x + 1; }");
+
+        assert!(matches!(returned(&body), Expr::Binary(_)));
+    }
+
+    #[test]
+    fn removes_code_that_never_runs() {
+        let body = optimize_fn(
+            "fn f() { if false { print(1); } while 1 > 2 { print(2); } \
+             return 3; print(4); }",
+        );
+
+        assert_eq!(body.len(), 1);
+        assert!(matches!(returned(&body), Expr::NumberLiteral(3)));
+
+        let body = optimize_fn(r#"fn f() { throw "x"; return 1; }"#);
+
+        assert!(matches!(body.as_slice(), [Stmt::Throw(_)]));
+    }
+}
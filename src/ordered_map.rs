@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A hash map that remembers the order its keys were first inserted in, and
+/// iterates in that order.
+pub struct OrderedMap<K, V> {
+    /// The entries in insertion order.
+    entries: Vec<(K, V)>,
+
+    /// The position of each key's entry.
+    indices: HashMap<K, usize>,
+}
+
+impl<K, V> Default for OrderedMap<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            indices: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> OrderedMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value for the key. A key that is already present keeps its
+    /// position, and its old value is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.indices.get(&key) {
+            Some(&index) => {
+                Some(std::mem::replace(&mut self.entries[index].1, value))
+            }
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.indices.get(key).map(|&index| &self.entries[index].1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> FromIterator<(K, V)> for OrderedMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterates_in_insertion_order() {
+        let mut map = OrderedMap::new();
+        map.insert("b", 1);
+        map.insert("a", 2);
+        map.insert("c", 3);
+
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), ["b", "a", "c"]);
+        assert_eq!(map.values().copied().collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn reinserting_keeps_the_position() {
+        let mut map = OrderedMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+
+        assert_eq!(map.insert("a", 3), Some(1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"a"), Some(&3));
+        assert_eq!(
+            map.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(),
+            [("a", 3), ("b", 2)]
+        );
+    }
+
+    #[test]
+    fn collects_with_later_values_winning() {
+        let map = [("x", 1), ("y", 2), ("x", 3)]
+            .into_iter()
+            .collect::<OrderedMap<_, _>>();
+
+        assert!(map.contains_key(&"y"));
+        assert!(!map.contains_key(&"z"));
+        assert_eq!(map.get(&"x"), Some(&3));
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), ["x", "y"]);
+    }
+}
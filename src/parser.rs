@@ -1,4 +1,6 @@
 use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
 
 use crate::lexer::*;
 use crate::syntax::*;
@@ -6,10 +8,40 @@ use crate::value::Operator;
 
 #[derive(Debug)]
 pub enum ParseError {
-    ExpectedToken(TokenKind, TokenKind), // expected, received
+    /// The token that was expected, and the one found in its place.
+    ExpectedToken(TokenKind, TokenKind, Span),
     EndOfFile,
+    /// A number literal that does not fit in a number.
+    InvalidNumber(String, Span),
+    InvalidChar(char, Span),
+    /// A string literal that is still open at the end of the file.
+    UnterminatedString(Span),
 }
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExpectedToken(exp, rec, span) => {
+                write!(f, "Expected {:?}, got {:?} at {}", exp, rec, span)
+            }
+            Self::EndOfFile => {
+                write!(f, "Expected token but reached end of file")
+            }
+            Self::InvalidNumber(text, span) => {
+                write!(f, "Number {} at {} is too large", text, span)
+            }
+            Self::InvalidChar(char, span) => {
+                write!(f, "Invalid character {:?} at {}", char, span)
+            }
+            Self::UnterminatedString(span) => {
+                write!(f, "String starting at {} is never closed", span)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
 const DEBUG_ENABLED: bool = false;
 
 pub struct Parser {
@@ -39,15 +71,12 @@ impl Parser {
         }
     }
 
-    fn peek(&self, offset: isize) -> Option<&Token> {
-        let index = self.position as isize + offset;
-        self.tokens.get(index as usize)
-    }
-
     fn consume(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
         match self.tokens.pop_front() {
             Some(token) if token.kind == kind => Ok(token),
-            Some(token) => Err(ParseError::ExpectedToken(kind, token.kind)),
+            Some(token) => {
+                Err(ParseError::ExpectedToken(kind, token.kind, token.span))
+            }
             None => Err(ParseError::EndOfFile),
         }
     }
@@ -70,18 +99,85 @@ impl Parser {
         Ok(decls)
     }
 
+    /// Parses an expression that makes up the whole input.
+    pub fn parse_lone_expr(mut self) -> Result<Expr, ParseError> {
+        let expr = self.parse_expr()?;
+        self.consume(TokenKind::EndOfFile)?;
+
+        Ok(expr)
+    }
+
     fn parse_decl(&mut self) -> Result<Decl, ParseError> {
         self.debug("parse decl");
 
         let current = self.current().ok_or(ParseError::EndOfFile)?;
 
-        match current.text.as_str() {
-            "fn" => Ok(Decl::FnDecl(self.parse_fn_decl()?)),
-            _ => panic!("Unknown declaration type: {}", current.text),
+        match current.kind {
+            TokenKind::ImportKeyword => {
+                Ok(Decl::Import(self.parse_import_decl()?))
+            }
+            TokenKind::PubKeyword => {
+                self.consume(TokenKind::PubKeyword)?;
+
+                let current = self.current().ok_or(ParseError::EndOfFile)?;
+
+                match current.kind {
+                    TokenKind::LetKeyword | TokenKind::ConstKeyword => Ok(
+                        Decl::Stmt(Stmt::Global(self.parse_global_stmt(true)?)),
+                    ),
+                    _ => Ok(Decl::FnDecl(self.parse_fn_decl(true)?)),
+                }
+            }
+            TokenKind::FnKeyword => {
+                Ok(Decl::FnDecl(self.parse_fn_decl(false)?))
+            }
+            TokenKind::LetKeyword | TokenKind::ConstKeyword => {
+                Ok(Decl::Stmt(Stmt::Global(self.parse_global_stmt(false)?)))
+            }
+            _ => Ok(Decl::Stmt(self.parse_stmt()?)),
         }
     }
 
-    fn parse_fn_decl(&mut self) -> Result<FnDecl, ParseError> {
+    fn parse_import_decl(&mut self) -> Result<ImportDecl, ParseError> {
+        self.debug("parse import decl");
+
+        let span = self.consume(TokenKind::ImportKeyword)?.span;
+        let path = self.consume(TokenKind::String)?.text;
+        self.consume(TokenKind::AsKeyword)?;
+        let alias = self.consume(TokenKind::Identifer)?.text;
+        self.consume(TokenKind::Semicolon)?;
+
+        Ok(ImportDecl {
+            path: path[1..path.len() - 1].to_owned(),
+            alias,
+            span,
+        })
+    }
+
+    fn parse_global_stmt(
+        &mut self,
+        public: bool,
+    ) -> Result<GlobalStmt, ParseError> {
+        self.debug("parse global stmt");
+
+        let current = self.current().ok_or(ParseError::EndOfFile)?;
+        let constant = current.kind == TokenKind::ConstKeyword;
+
+        self.consume(current.kind)?;
+        let var = self.consume(TokenKind::Identifer)?.text;
+        self.consume(TokenKind::Equals)?;
+        let val = self.parse_expr()?;
+        self.consume(TokenKind::Semicolon)?;
+
+        Ok(GlobalStmt {
+            var,
+            val,
+            constant,
+            public,
+        })
+    }
+
+    fn parse_fn_decl(&mut self, public: bool) -> Result<FnDecl, ParseError> {
         self.debug("parse fn decl");
 
         self.consume(TokenKind::FnKeyword)?;
@@ -93,7 +189,12 @@ impl Parser {
         let body = self.parse_body()?;
         self.consume(TokenKind::RightBrace)?;
 
-        Ok(FnDecl { name, params, body })
+        Ok(FnDecl {
+            name,
+            params,
+            body,
+            public,
+        })
     }
 
     fn parse_params(&mut self) -> Result<Vec<String>, ParseError> {
@@ -183,9 +284,7 @@ impl Parser {
 
         let mut left = self.parse_unary_expr()?;
 
-        loop {
-            let Some(current) = self.current() else { break };
-
+        while let Some(current) = self.current() {
             let Ok(op) = Operator::try_from(current.kind) else {
                 break;
             };
@@ -196,13 +295,14 @@ impl Parser {
                 break;
             }
 
-            self.consume(current.kind)?;
+            let span = self.consume(current.kind)?.span;
             let right = self.parse_binary_expr(prec)?;
 
             left = Expr::Binary(Binary {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                span,
             });
         }
 
@@ -218,12 +318,13 @@ impl Parser {
 
         match current.kind {
             TokenKind::Minus => {
-                self.consume(TokenKind::Minus)?;
+                let span = self.consume(TokenKind::Minus)?.span;
                 let inner = self.parse_unary_expr()?;
 
                 Ok(Expr::Unary(Unary {
                     op: Operator::Minus,
                     expr: Box::new(inner),
+                    span,
                 }))
             }
             _ => self.parse_primary_expr(),
@@ -237,8 +338,10 @@ impl Parser {
 
         match current.kind {
             TokenKind::Number => {
-                let arg = self.consume(TokenKind::Number)?.text;
-                let value = arg.parse::<i32>().unwrap();
+                let arg = self.consume(TokenKind::Number)?;
+                let value = arg.text.parse::<i32>().map_err(|_| {
+                    ParseError::InvalidNumber(arg.text, arg.span)
+                })?;
                 Ok(Expr::NumberLiteral(value))
             }
             TokenKind::String => {
@@ -254,12 +357,8 @@ impl Parser {
                 Ok(Expr::BooleanLiteral(false))
             }
             TokenKind::Identifer => {
-                if self.peek(1).unwrap().kind == TokenKind::LeftParen {
-                    Ok(Expr::FnCall(self.parse_fn_call()?))
-                } else {
-                    let ident = self.consume(TokenKind::Identifer)?.text;
-                    Ok(Expr::Identfier(ident))
-                }
+                let var = self.consume(TokenKind::Identifer)?;
+                self.parse_var_or_call(var)
             }
             TokenKind::NullKeyword => {
                 self.consume(TokenKind::NullKeyword)?;
@@ -277,7 +376,10 @@ impl Parser {
                 self.consume(TokenKind::RightBracket)?;
                 Ok(Expr::ListLiteral(values))
             }
-            _ => Ok(Expr::FnCall(self.parse_fn_call()?)),
+            _ => {
+                let name = self.parse_name()?;
+                Ok(Expr::FnCall(self.parse_fn_call(name)?))
+            }
         }
     }
 
@@ -300,7 +402,9 @@ impl Parser {
 
         fields.push((name, expr));
 
-        while self.current().unwrap().kind != TokenKind::RightBrace {
+        while self.current().ok_or(ParseError::EndOfFile)?.kind
+            != TokenKind::RightBrace
+        {
             self.consume(TokenKind::Comma)?;
 
             let name = self.consume(TokenKind::Identifer)?.text;
@@ -328,6 +432,10 @@ impl Parser {
             TokenKind::WhileKeyword => {
                 Ok(Stmt::While(self.parse_while_stmt()?))
             }
+            TokenKind::ThrowKeyword => {
+                Ok(Stmt::Throw(self.parse_throw_stmt()?))
+            }
+            TokenKind::TryKeyword => Ok(Stmt::Try(self.parse_try_stmt()?)),
             TokenKind::LetKeyword => {
                 self.consume(TokenKind::LetKeyword)?;
                 let var = self.consume(TokenKind::Identifer)?.text;
@@ -337,14 +445,15 @@ impl Parser {
                 Ok(Stmt::Decl(DeclStmt { var, val }))
             }
             _ => {
-                let next = self.peek(1).ok_or(ParseError::EndOfFile)?;
+                let name = self.parse_name()?;
+                let current = self.current().ok_or(ParseError::EndOfFile)?;
 
-                if next.kind == TokenKind::LeftParen {
-                    let stmt = Stmt::FnCall(self.parse_fn_call()?);
+                if current.kind == TokenKind::LeftParen {
+                    let stmt = Stmt::FnCall(self.parse_fn_call(name)?);
                     self.consume(TokenKind::Semicolon)?;
                     Ok(stmt)
                 } else {
-                    let stmt = Stmt::Assign(self.parse_assign()?);
+                    let stmt = Stmt::Assign(self.parse_assign(name)?);
                     self.consume(TokenKind::Semicolon)?;
                     Ok(stmt)
                 }
@@ -352,36 +461,97 @@ impl Parser {
         }
     }
 
-    fn parse_assign(&mut self) -> Result<AssignStmt, ParseError> {
-        let var = self.consume(TokenKind::Identifer)?.text;
+    fn parse_assign(&mut self, var: Token) -> Result<AssignStmt, ParseError> {
         self.consume(TokenKind::Equals)?;
         let val = self.parse_expr()?;
 
-        Ok(AssignStmt { var, val })
+        Ok(AssignStmt {
+            var: var.text,
+            val,
+            span: var.span,
+        })
     }
 
     fn parse_if_stmt(&mut self) -> Result<IfStmt, ParseError> {
         self.debug("parse if stmt");
 
-        self.consume(TokenKind::IfKeyword)?;
+        let span = self.consume(TokenKind::IfKeyword)?.span;
         let cond = self.parse_expr()?;
         self.consume(TokenKind::LeftBrace)?;
         let body = self.parse_body()?;
         self.consume(TokenKind::RightBrace)?;
 
-        Ok(IfStmt { cond, body })
+        Ok(IfStmt { cond, body, span })
     }
 
     fn parse_while_stmt(&mut self) -> Result<WhileStmt, ParseError> {
         self.debug("parse while stmt");
 
-        self.consume(TokenKind::WhileKeyword)?;
+        let span = self.consume(TokenKind::WhileKeyword)?.span;
         let cond = self.parse_expr()?;
         self.consume(TokenKind::LeftBrace)?;
         let body = self.parse_body()?;
         self.consume(TokenKind::RightBrace)?;
 
-        Ok(WhileStmt { cond, body })
+        Ok(WhileStmt { cond, body, span })
+    }
+
+    fn parse_throw_stmt(&mut self) -> Result<ThrowStmt, ParseError> {
+        self.debug("parse throw stmt");
+
+        let span = self.consume(TokenKind::ThrowKeyword)?.span;
+        let expr = self.parse_expr()?;
+        self.consume(TokenKind::Semicolon)?;
+
+        Ok(ThrowStmt { expr, span })
+    }
+
+    fn parse_try_stmt(&mut self) -> Result<TryStmt, ParseError> {
+        self.debug("parse try stmt");
+
+        self.consume(TokenKind::TryKeyword)?;
+        self.consume(TokenKind::LeftBrace)?;
+        let body = self.parse_body()?;
+        self.consume(TokenKind::RightBrace)?;
+
+        let mut catch = None;
+        let mut finally = None;
+
+        if let Some(TokenKind::CatchKeyword) = self.current().map(|t| t.kind) {
+            self.consume(TokenKind::CatchKeyword)?;
+            self.consume(TokenKind::LeftParen)?;
+            let var = self.consume(TokenKind::Identifer)?.text;
+            self.consume(TokenKind::RightParen)?;
+            self.consume(TokenKind::LeftBrace)?;
+            let body = self.parse_body()?;
+            self.consume(TokenKind::RightBrace)?;
+
+            catch = Some(CatchClause { var, body });
+        }
+
+        if let Some(TokenKind::FinallyKeyword) = self.current().map(|t| t.kind)
+        {
+            self.consume(TokenKind::FinallyKeyword)?;
+            self.consume(TokenKind::LeftBrace)?;
+            finally = Some(self.parse_body()?);
+            self.consume(TokenKind::RightBrace)?;
+        }
+
+        if catch.is_none() && finally.is_none() {
+            let current = self.current().ok_or(ParseError::EndOfFile)?;
+
+            return Err(ParseError::ExpectedToken(
+                TokenKind::CatchKeyword,
+                current.kind,
+                current.span,
+            ));
+        }
+
+        Ok(TryStmt {
+            body,
+            catch,
+            finally,
+        })
     }
 
     fn parse_return_stmt(&mut self) -> Result<ReturnStmt, ParseError> {
@@ -408,14 +578,76 @@ impl Parser {
         Ok(stmt)
     }
 
-    fn parse_fn_call(&mut self) -> Result<FnCall, ParseError> {
+    /// Parses a name, which is qualified by a module as in `util.helper` if
+    /// it is made of two identifiers with a period between them.
+    fn parse_name(&mut self) -> Result<Token, ParseError> {
+        let mut name = self.consume(TokenKind::Identifer)?;
+
+        if let Some(TokenKind::Period) = self.current().map(|token| token.kind)
+        {
+            self.consume(TokenKind::Period)?;
+            let member = self.consume(TokenKind::Identifer)?;
+            name.text = format!("{}.{}", name.text, member.text);
+        }
+
+        Ok(name)
+    }
+
+    /// Parses what follows the name of a variable in an expression. That is
+    /// a call if parentheses follow, with a qualified name if it is preceded
+    /// by a field as in `util.helper()`, and otherwise the fields read from
+    /// the variable, as in `error.kind`.
+    fn parse_var_or_call(&mut self, var: Token) -> Result<Expr, ParseError> {
+        let mut expr = Expr::Identfier(var.text.clone(), var.span);
+
+        while let Some(TokenKind::Period) =
+            self.current().map(|token| token.kind)
+        {
+            self.consume(TokenKind::Period)?;
+            let field = self.consume(TokenKind::Identifer)?;
+
+            expr = Expr::FieldAccess(FieldAccess {
+                obj: Box::new(expr),
+                field: field.text,
+                span: field.span,
+            });
+        }
+
+        let Some(TokenKind::LeftParen) = self.current().map(|token| token.kind)
+        else {
+            return Ok(expr);
+        };
+
+        // Only a function or a member of a module can be called, so the
+        // parentheses after any other fields are left as an error.
+        let name = match &expr {
+            Expr::Identfier(..) => var,
+            Expr::FieldAccess(FieldAccess { obj, field, .. })
+                if matches!(**obj, Expr::Identfier(..)) =>
+            {
+                Token {
+                    text: format!("{}.{}", var.text, field),
+                    ..var
+                }
+            }
+            _ => return Ok(expr),
+        };
+
+        Ok(Expr::FnCall(self.parse_fn_call(name)?))
+    }
+
+    fn parse_fn_call(&mut self, name: Token) -> Result<FnCall, ParseError> {
         self.debug("parse fn call");
 
-        let name = self.consume(TokenKind::Identifer)?.text;
         self.consume(TokenKind::LeftParen)?;
         let args = self.parse_expr_list(TokenKind::RightParen)?;
         self.consume(TokenKind::RightParen)?;
 
-        Ok(FnCall { name, args })
+        Ok(FnCall {
+            name: name.text,
+            args,
+            span: name.span,
+            fn_names: None,
+        })
     }
 }
@@ -0,0 +1,68 @@
+fn range(start, end) {
+    let items = [];
+    let next = start;
+
+    while next < end {
+        append(items, next);
+        next = next + 1;
+    }
+
+    return items;
+}
+
+fn sum(items) {
+    let total = 0;
+    let index = 0;
+
+    while index < len(items) {
+        total = total + get(items, index);
+        index = index + 1;
+    }
+
+    return total;
+}
+
+fn max_of(items) {
+    if len(items) == 0 {
+        return null;
+    }
+
+    let max = get(items, 0);
+    let index = 1;
+
+    while index < len(items) {
+        let item = get(items, index);
+
+        if item > max {
+            max = item;
+        }
+
+        index = index + 1;
+    }
+
+    return max;
+}
+
+fn contains(items, value) {
+    let index = 0;
+
+    while index < len(items) {
+        if get(items, index) == value {
+            return true;
+        }
+
+        index = index + 1;
+    }
+
+    return false;
+}
+
+fn repeat(value, count) {
+    let items = [];
+
+    while len(items) < count {
+        append(items, value);
+    }
+
+    return items;
+}
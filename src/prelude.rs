@@ -0,0 +1,77 @@
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::syntax::Decl;
+
+/// The source of the standard library, which is written in R-Lang and loaded
+/// before the program so that its functions can be called like builtins.
+pub const SOURCE: &str = include_str!("prelude.rl");
+
+/// Parses the standard library into declarations to load before those of the
+/// program, which can replace its functions by declaring their names again.
+pub fn decls() -> Vec<Decl> {
+    let tokens = Lexer::new(SOURCE.to_owned())
+        .scan_tokens()
+        .expect("the prelude is valid");
+
+    Parser::new(tokens).parse().expect("the prelude is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::value::Value;
+
+    fn call(engine: &mut Engine, source: &str) -> String {
+        engine
+            .load(&format!("fn test() {{ return {}; }}", source))
+            .unwrap();
+        engine.call("test", vec![]).unwrap().to_string()
+    }
+
+    #[test]
+    fn declares_its_functions() {
+        let names = decls()
+            .into_iter()
+            .filter_map(|decl| match decl {
+                Decl::FnDecl(fn_decl) => Some(fn_decl.name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["range", "sum", "max_of", "contains", "repeat"]);
+    }
+
+    #[test]
+    fn functions_work() {
+        let mut engine = Engine::new();
+
+        assert_eq!(call(&mut engine, "range(2, 5)"), "[ 2, 3, 4 ]");
+        assert_eq!(call(&mut engine, "len(range(3, 3))"), "0");
+        assert_eq!(call(&mut engine, "sum(range(1, 5))"), "10");
+        assert_eq!(call(&mut engine, "max_of([3, 9, 2])"), "9");
+        assert_eq!(call(&mut engine, "max_of([])"), "null");
+        assert_eq!(call(&mut engine, "contains([1, [2]], [2])"), "true");
+        assert_eq!(call(&mut engine, "contains([1], 2)"), "false");
+        assert_eq!(call(&mut engine, r#"repeat("a", 2)"#), "[ a, a ]");
+    }
+
+    #[test]
+    fn can_be_left_out() {
+        let mut engine = Engine::without_prelude();
+        engine.load("fn test() { return range(0, 1); }").unwrap();
+
+        assert!(!engine.has_fn("range"));
+        assert!(engine.call("test", vec![]).is_err());
+    }
+
+    #[test]
+    fn programs_can_replace_its_functions() {
+        let mut engine = Engine::new();
+        engine.load("fn sum(items) { return len(items); }").unwrap();
+
+        let total = engine.call("sum", vec![Value::List(Default::default())]);
+        assert_eq!(total.unwrap().to_string(), "0");
+        assert_eq!(call(&mut engine, "sum([5, 6])"), "2");
+    }
+}
@@ -62,6 +62,52 @@ impl TreePrint for Stmt {
                 display(" val:", indent);
                 val.print(indent + 1);
             }
+            Stmt::Global(GlobalStmt {
+                var,
+                val,
+                constant,
+                public,
+            }) => {
+                display("GlobalStmt", indent);
+                display(" var:", indent);
+                display(var, indent + 1);
+                display(" val:", indent);
+                val.print(indent + 1);
+                display(" constant:", indent);
+                display(constant.to_string(), indent + 1);
+                display(" public:", indent);
+                display(public.to_string(), indent + 1);
+            }
+            Stmt::Throw(throw_stmt) => {
+                display("ThrowStmt", indent);
+                display(" expr:", indent);
+                throw_stmt.expr.print(indent + 1);
+            }
+            Stmt::Try(try_stmt) => {
+                display("TryStmt", indent);
+                display(" body:", indent);
+
+                for stmt in try_stmt.body.iter() {
+                    stmt.print(indent + 1);
+                }
+
+                if let Some(catch) = &try_stmt.catch {
+                    display(" catch:", indent);
+                    display(&catch.var, indent + 1);
+
+                    for stmt in catch.body.iter() {
+                        stmt.print(indent + 1);
+                    }
+                }
+
+                if let Some(finally) = &try_stmt.finally {
+                    display(" finally:", indent);
+
+                    for stmt in finally.iter() {
+                        stmt.print(indent + 1);
+                    }
+                }
+            }
         }
     }
 }
@@ -69,7 +115,7 @@ impl TreePrint for Stmt {
 impl TreePrint for Expr {
     fn print(&self, indent: usize) {
         match self {
-            Expr::Identfier(name) => {
+            Expr::Identfier(name, _) => {
                 display(format!("Identifier('{}')", name), indent)
             }
             Expr::NumberLiteral(value) => {
@@ -126,7 +172,13 @@ impl TreePrint for Expr {
                     value.print(indent + 1);
                 }
             }
-            Expr::FieldAccess(_) => todo!(),
+            Expr::FieldAccess(field_access) => {
+                display("FieldAccess", indent);
+                display(" obj:", indent);
+                field_access.obj.print(indent + 1);
+                display(" field:", indent);
+                display(&field_access.field, indent + 1);
+            }
         }
     }
 }
@@ -138,6 +190,8 @@ impl TreePrint for Decl {
                 display("FnDecl", indent);
                 display(" name:", indent);
                 display(&fn_decl.name, indent + 1);
+                display(" public:", indent);
+                display(fn_decl.public.to_string(), indent + 1);
                 display(" params:", indent);
 
                 for param in fn_decl.params.iter() {
@@ -150,6 +204,14 @@ impl TreePrint for Decl {
                     stmt.print(indent + 1);
                 }
             }
+            Decl::Stmt(stmt) => stmt.print(indent),
+            Decl::Import(import) => {
+                display("ImportDecl", indent);
+                display(" path:", indent);
+                display(&import.path, indent + 1);
+                display(" alias:", indent);
+                display(&import.alias, indent + 1);
+            }
         }
     }
 }
@@ -0,0 +1,235 @@
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use rlang::interpreter::*;
+use rlang::lexer::*;
+use rlang::modules::ModuleLoader;
+use rlang::parser::*;
+use rlang::printing::TreePrint;
+use rlang::syntax::*;
+use rlang::value::Value;
+
+const HELP: &str = "\
+Type statements, expressions or function declarations to run them.
+Brackets left open continue the input on the next line.
+
+Commands:
+  :ast CODE     Prints the syntax tree of the code
+  :tokens CODE  Prints the tokens of the code
+  :history      Prints everything typed in this session
+  :reset        Forgets all variables, functions and modules
+  :help         Prints this message
+  :quit         Leaves the REPL";
+
+/// An interactive session that runs each input in the same interpreter, so
+/// that the variables and functions it defines carry over to the next one.
+pub struct Repl<F> {
+    /// Creates the interpreter when the session starts or is reset.
+    new_interpreter: F,
+    interpreter: Interpreter,
+    /// Loads the modules imported so far, relative to the current directory.
+    modules: ModuleLoader,
+    /// Every input of the session, oldest first.
+    history: Vec<String>,
+}
+
+impl<F: Fn() -> Interpreter> Repl<F> {
+    pub fn new(new_interpreter: F) -> Self {
+        Self {
+            interpreter: new_interpreter(),
+            new_interpreter,
+            modules: ModuleLoader::new(),
+            history: vec![],
+        }
+    }
+
+    /// Reads and runs inputs until the input ends or `:quit` is typed.
+    pub fn run(&mut self) -> io::Result<()> {
+        println!("R-Lang REPL, type :help for a list of commands");
+
+        loop {
+            let Some(input) = read_input()? else {
+                // Ends the line of the last prompt.
+                println!();
+                break;
+            };
+
+            let input = input.trim();
+
+            if input.is_empty() {
+                continue;
+            }
+
+            self.history.push(input.to_owned());
+
+            let res = match input.split_once(char::is_whitespace) {
+                _ if !input.starts_with(':') => self.eval(input),
+                Some((":ast", code)) => print_ast(code),
+                Some((":tokens", code)) => print_tokens(code),
+                None => match input {
+                    ":history" => {
+                        self.print_history();
+                        Ok(())
+                    }
+                    ":reset" => {
+                        self.interpreter = (self.new_interpreter)();
+                        self.modules = ModuleLoader::new();
+                        println!("Forgot all variables, functions and modules");
+                        Ok(())
+                    }
+                    ":help" => {
+                        println!("{}", HELP);
+                        Ok(())
+                    }
+                    ":quit" => break,
+                    ":ast" | ":tokens" => Err(format!("Usage: {} CODE", input)),
+                    _ => Err(format!("Unknown command: {}", input)),
+                },
+                Some(_) => Err(format!("Unknown command: {}", input)),
+            };
+
+            if let Err(msg) = res {
+                eprintln!("{}", msg);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the input, echoing its value if it is an expression.
+    fn eval(&mut self, input: &str) -> Result<(), String> {
+        let (decls, echo) = match parse(input)? {
+            Input::Expr(expr) => {
+                let stmt = Stmt::Return(ReturnStmt { expr });
+                (vec![Decl::Stmt(stmt)], true)
+            }
+            Input::Program(decls) => (decls, false),
+        };
+
+        let decls = self
+            .modules
+            .load(Path::new("<input>"), decls)
+            .map_err(|err| format!("Module error: {}", err))?;
+
+        let stmts = self.interpreter.load(decls);
+        let value = self.interpreter.exec(&stmts).map_err(report)?;
+
+        if echo && !matches!(value, Value::Null) {
+            println!("{}", value);
+        }
+
+        Ok(())
+    }
+
+    fn print_history(&self) {
+        for (index, input) in self.history.iter().enumerate() {
+            println!("{:>4}  {}", index + 1, input);
+        }
+    }
+}
+
+/// What a single input to the REPL turned out to be.
+enum Input {
+    Expr(Expr),
+    /// Function declarations and statements, as in a program.
+    Program(Vec<Decl>),
+}
+
+/// Parses the input as a lone expression, and otherwise as a program.
+fn parse(input: &str) -> Result<Input, String> {
+    let parse_err = |err: ParseError| format!("Parse error: {}", err);
+
+    let tokens = Lexer::new(input.to_owned())
+        .scan_tokens()
+        .map_err(parse_err)?;
+
+    if let Ok(expr) = Parser::new(tokens.clone()).parse_lone_expr() {
+        return Ok(Input::Expr(expr));
+    }
+
+    Parser::new(tokens)
+        .parse()
+        .map(Input::Program)
+        .map_err(parse_err)
+}
+
+fn report(err: RuntimeError) -> String {
+    format!("Runtime error: {}", err)
+}
+
+fn print_ast(code: &str) -> Result<(), String> {
+    match parse(code)? {
+        Input::Expr(expr) => expr.print(0),
+        Input::Program(decls) => decls.iter().for_each(|decl| decl.print(0)),
+    }
+
+    Ok(())
+}
+
+fn print_tokens(code: &str) -> Result<(), String> {
+    let tokens = Lexer::new(code.to_owned())
+        .scan_tokens()
+        .map_err(|err| format!("Parse error: {}", err))?;
+
+    for token in tokens {
+        if token.kind != TokenKind::EndOfFile {
+            println!("{:?} {:?} at {}", token.kind, token.text, token.span);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads one input, which carries on over several lines while it has
+/// brackets or a string left open. Returns `None` once the input has ended.
+fn read_input() -> io::Result<Option<String>> {
+    let mut input = String::new();
+
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { "... " });
+        io::stdout().flush()?;
+
+        // The standard input is not locked for the whole session, since the
+        // program may read from it too.
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok((!input.is_empty()).then_some(input));
+        }
+
+        if !is_incomplete(&input) {
+            return Ok(Some(input));
+        }
+    }
+}
+
+/// Returns whether the source opens more brackets than it closes, or leaves
+/// a string open, not counting brackets inside strings.
+fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    let mut in_string = false;
+
+    for char in source.chars() {
+        match char {
+            '"' => in_string = !in_string,
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth > 0 || in_string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_brackets_and_strings_continue_the_input() {
+        assert!(is_incomplete("fn f() {\n"));
+        assert!(is_incomplete("print([1,\n"));
+        assert!(is_incomplete("print(\"a\n"));
+        assert!(!is_incomplete("print(\"{\");\n"));
+        assert!(!is_incomplete("fn f() { return 1; }\n"));
+    }
+}
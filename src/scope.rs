@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use crate::interpreter::RuntimeError;
 use crate::value::Value;
 
 /// Holds a scope to allow push and pop operations.
@@ -7,6 +8,17 @@ use crate::value::Value;
 pub struct ScopeManager {
     /// The optional current scope, if it exists.
     scope: Option<Scope>,
+
+    /// The variables declared at the top level of the program, which every
+    /// function can see.
+    globals: HashMap<String, Global>,
+}
+
+/// A variable declared at the top level of the program.
+struct Global {
+    value: Value,
+    /// Whether it was declared with `const`, so that it cannot be assigned.
+    constant: bool,
 }
 
 impl ScopeManager {
@@ -19,22 +31,71 @@ impl ScopeManager {
 
     /// Removes the last level from the scope hierarchy.
     pub fn pop_scope(&mut self) -> Result<(), ()> {
-        let current_scope = self.scope.take().ok_or(())?;
+        let mut current_scope = self.scope.take().ok_or(())?;
 
-        self.scope = current_scope.parent.map(|s| *s);
+        self.scope = current_scope.parent.take().map(|s| *s);
 
         Ok(())
     }
 
-    /// Returns a shared reference to the inner scope.
-    pub fn inner(&self) -> Option<&Scope> {
-        self.scope.as_ref()
-    }
-
     // Returns an exclusive reference to the inner scope.
     pub fn inner_mut(&mut self) -> Option<&mut Scope> {
         self.scope.as_mut()
     }
+
+    /// Returns a shared reference to the value of the variable with the given
+    /// name, looking through the scopes before the globals.
+    pub fn get(&self, var_name: &str) -> Option<&Value> {
+        self.scope
+            .as_ref()
+            .and_then(|scope| scope.get(var_name))
+            .or_else(|| self.get_global(var_name))
+    }
+
+    /// Sets the value of the variable with the given name, looking through
+    /// the scopes before the globals.
+    pub fn set(
+        &mut self,
+        var_name: &str,
+        new_value: Value,
+    ) -> Result<(), RuntimeError> {
+        if let Some(value) = self
+            .scope
+            .as_mut()
+            .and_then(|scope| scope.get_mut(var_name))
+        {
+            *value = new_value;
+            return Ok(());
+        }
+
+        match self.globals.get_mut(var_name) {
+            Some(global) if global.constant => {
+                Err(RuntimeError::ConstantAssignment(var_name.to_owned()))
+            }
+            Some(global) => {
+                global.value = new_value;
+                Ok(())
+            }
+            None => Err(RuntimeError::UndefinedIdentifier(var_name.to_owned())),
+        }
+    }
+
+    /// Returns a shared reference to the value of the global variable with
+    /// the given name.
+    pub fn get_global(&self, var_name: &str) -> Option<&Value> {
+        self.globals.get(var_name).map(|global| &global.value)
+    }
+
+    /// Declares a global variable with the given name, replacing any that
+    /// already exists.
+    pub fn declare_global(
+        &mut self,
+        var_name: String,
+        value: Value,
+        constant: bool,
+    ) {
+        self.globals.insert(var_name, Global { value, constant });
+    }
 }
 
 /// Holds the variables present at the current level of execution.
@@ -57,33 +118,26 @@ impl Scope {
 
     /// Returns a shared reference to the value with the given name.
     pub fn get(&self, var_name: &str) -> Option<&Value> {
-        if let var @ Some(_) = self.variables.get(var_name) {
-            return var;
-        }
+        let mut scope = self;
+
+        loop {
+            if let var @ Some(_) = scope.variables.get(var_name) {
+                return var;
+            }
 
-        match self.parent {
-            Some(ref parent) => parent.get(var_name),
-            None => None,
+            scope = scope.parent.as_deref()?;
         }
     }
 
     /// Returns an exclusive reference to the value with the given name.
     fn get_mut(&mut self, var_name: &str) -> Option<&mut Value> {
-        if let value @ Some(_) = self.variables.get_mut(var_name) {
-            return value;
-        }
+        let mut scope = self;
 
-        match self.parent {
-            Some(ref mut parent) => parent.get_mut(var_name),
-            None => None,
+        while !scope.variables.contains_key(var_name) {
+            scope = scope.parent.as_deref_mut()?;
         }
-    }
 
-    /// Sets the value of the variable with the given name to the given value.
-    pub fn set(&mut self, var_name: &str, new_value: Value) -> Result<(), ()> {
-        self.get_mut(var_name)
-            .map(|value| *value = new_value)
-            .ok_or(())
+        scope.variables.get_mut(var_name)
     }
 
     /// Declares a new variable with the given name in the current scope, giving
@@ -92,3 +146,15 @@ impl Scope {
         self.variables.insert(var_name, initial_value);
     }
 }
+
+impl Drop for Scope {
+    /// Drops the parent scopes one at a time, since dropping them recursively
+    /// could overflow the stack when scripts recurse deeply.
+    fn drop(&mut self) {
+        let mut parent = self.parent.take();
+
+        while let Some(mut scope) = parent {
+            parent = scope.parent.take();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::rc::Rc;
+
+/// The streams that scripts print to and read input from, which are the
+/// process's standard streams unless the host replaces them.
+pub struct Streams {
+    pub stdout: RefCell<Box<dyn Write>>,
+    pub stderr: RefCell<Box<dyn Write>>,
+    pub stdin: RefCell<Box<dyn BufRead>>,
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Self {
+            stdout: RefCell::new(Box::new(io::stdout())),
+            stderr: RefCell::new(Box::new(io::stderr())),
+            stdin: RefCell::new(Box::new(BufReader::new(io::stdin()))),
+        }
+    }
+}
+
+impl Streams {
+    pub fn set_stdout(&self, stdout: impl Write + 'static) {
+        self.stdout.replace(Box::new(stdout));
+    }
+
+    pub fn set_stderr(&self, stderr: impl Write + 'static) {
+        self.stderr.replace(Box::new(stderr));
+    }
+
+    pub fn set_stdin(&self, stdin: impl Read + 'static) {
+        self.stdin.replace(Box::new(BufReader::new(stdin)));
+    }
+}
+
+/// An in-memory stream that output can be written to and read back from,
+/// such as to capture what a script prints. Clones share the same contents,
+/// so one can be given to the interpreter while the host keeps another.
+#[derive(Clone, Default)]
+pub struct SharedBuffer {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns everything written so far, replacing invalid UTF-8.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.borrow()).into_owned()
+    }
+
+    /// Returns everything written so far and empties the buffer.
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.bytes.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
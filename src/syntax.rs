@@ -1,13 +1,32 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::lexer::Span;
 use crate::value::Operator;
 
 pub enum Decl {
     FnDecl(FnDecl),
+    /// A statement at the top level of the program, which runs in order with
+    /// the others before `main` is called.
+    Stmt(Stmt),
+    Import(ImportDecl),
 }
 
 pub struct FnDecl {
     pub name: String,
     pub params: Vec<String>,
     pub body: Vec<Stmt>,
+    /// Whether the function is marked `pub`, so that modules importing this
+    /// one can call it.
+    pub public: bool,
+}
+
+/// An `import` of the module at the path, whose public functions and globals
+/// are then reached through the alias, as in `alias.name`.
+pub struct ImportDecl {
+    pub path: String,
+    pub alias: String,
+    pub span: Span,
 }
 
 pub enum Stmt {
@@ -17,11 +36,15 @@ pub enum Stmt {
     While(WhileStmt),
     Assign(AssignStmt),
     Decl(DeclStmt),
+    Global(GlobalStmt),
+    Throw(ThrowStmt),
+    Try(TryStmt),
 }
 
 pub struct AssignStmt {
     pub var: String,
     pub val: Expr,
+    pub span: Span,
 }
 
 pub struct DeclStmt {
@@ -29,6 +52,17 @@ pub struct DeclStmt {
     pub val: Expr,
 }
 
+/// A `let` or `const` declaration at the top level of the program, which
+/// declares a global variable.
+pub struct GlobalStmt {
+    pub var: String,
+    pub val: Expr,
+    pub constant: bool,
+    /// Whether the global is marked `pub`, so that modules importing this one
+    /// can use it.
+    pub public: bool,
+}
+
 pub struct ReturnStmt {
     pub expr: Expr,
 }
@@ -36,20 +70,43 @@ pub struct ReturnStmt {
 pub struct IfStmt {
     pub cond: Expr,
     pub body: Vec<Stmt>,
+    pub span: Span,
 }
 
 pub struct WhileStmt {
     pub cond: Expr,
     pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+pub struct ThrowStmt {
+    pub expr: Expr,
+    pub span: Span,
+}
+
+/// A `try` statement, which has a `catch` clause, a `finally` body or both.
+pub struct TryStmt {
+    pub body: Vec<Stmt>,
+    pub catch: Option<CatchClause>,
+    pub finally: Option<Vec<Stmt>>,
+}
+
+pub struct CatchClause {
+    /// The variable the caught error is assigned to.
+    pub var: String,
+    pub body: Vec<Stmt>,
 }
 
 pub enum Expr {
-    Identfier(String),
+    /// A variable, whose name is qualified as in `util.name` if it is a
+    /// global of an imported module.
+    Identfier(String, Span),
     NumberLiteral(i32),
     BooleanLiteral(bool),
     StringLiteral(String),
     NullLiteral,
     FnCall(FnCall),
+    /// A field read from an object, as in `error.kind`.
     FieldAccess(FieldAccess),
     ObjectLiteral(Vec<(String, Expr)>),
     ListLiteral(Vec<Expr>),
@@ -57,23 +114,89 @@ pub enum Expr {
     Unary(Unary),
 }
 
+impl Stmt {
+    /// Returns where the statement starts, if it can raise an error there.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::FnCall(FnCall { span, .. })
+            | Self::If(IfStmt { span, .. })
+            | Self::While(WhileStmt { span, .. })
+            | Self::Assign(AssignStmt { span, .. })
+            | Self::Throw(ThrowStmt { span, .. }) => Some(*span),
+            Self::Return(ReturnStmt { expr })
+            | Self::Decl(DeclStmt { val: expr, .. })
+            | Self::Global(GlobalStmt { val: expr, .. }) => expr.span(),
+            Self::Try(_) => None,
+        }
+    }
+}
+
+impl Expr {
+    /// Returns where the expression starts, if it can raise an error there.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Identfier(_, span) => Some(*span),
+            Self::FnCall(FnCall { span, .. })
+            | Self::FieldAccess(FieldAccess { span, .. })
+            | Self::Binary(Binary { span, .. })
+            | Self::Unary(Unary { span, .. }) => Some(*span),
+            _ => None,
+        }
+    }
+}
+
 pub struct FieldAccess {
     pub obj: Box<Expr>,
     pub field: String,
+    /// Where the field name is.
+    pub span: Span,
 }
 
 pub struct Binary {
     pub op: Operator,
     pub left: Box<Expr>,
     pub right: Box<Expr>,
+    /// Where the operator is.
+    pub span: Span,
 }
 
 pub struct Unary {
     pub op: Operator,
     pub expr: Box<Expr>,
+    pub span: Span,
 }
 
 pub struct FnCall {
+    /// The name of the function, which is qualified as in `util.helper` if it
+    /// is declared by an imported module.
     pub name: String,
     pub args: Vec<Expr>,
+    pub span: Span,
+    /// The functions the code making the call can name, for builtins that
+    /// are given the name of a function to call. It is `None` unless the
+    /// program was loaded along with its imports.
+    pub fn_names: Option<Rc<FnNames>>,
+}
+
+/// The names of the functions a module can call, mapped to the names they are
+/// known by once it is loaded.
+pub struct FnNames(HashMap<String, String>);
+
+impl FnNames {
+    pub fn new(names: HashMap<String, String>) -> Self {
+        Self(names)
+    }
+
+    /// Returns the name that the function the module calls by the given name
+    /// is known by, or `None` if the name is qualified as in `util.helper` but
+    /// no public function of an imported module has it. Other names are those
+    /// of builtins, or of functions declared elsewhere, and are left as they
+    /// are.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        match self.0.get(name) {
+            Some(name) => Some(name),
+            None if name.contains('.') => None,
+            None => Some(name),
+        }
+    }
 }
@@ -1,6 +1,10 @@
-use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc, sync::OnceLock};
+use std::{
+    cell::RefCell, cmp::Ordering, collections::HashMap, error::Error, fmt,
+    rc::Rc, sync::OnceLock,
+};
 
-use crate::TokenKind;
+use crate::lexer::TokenKind;
+use crate::ordered_map::OrderedMap;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Operator {
@@ -22,6 +26,7 @@ impl TryFrom<TokenKind> for Operator {
             TokenKind::Minus => Operator::Minus,
             TokenKind::Star => Operator::Star,
             TokenKind::Slash => Operator::Slash,
+            TokenKind::DoubleEquals => Operator::Equals,
             TokenKind::LessThan => Operator::LessThan,
             TokenKind::GreaterThan => Operator::GreaterThan,
             _ => return Err(()),
@@ -42,6 +47,7 @@ impl Operator {
             map.insert(Operator::Minus, 2);
             map.insert(Operator::Star, 3);
             map.insert(Operator::Slash, 3);
+            map.insert(Operator::Equals, 1);
             map.insert(Operator::LessThan, 1);
             map.insert(Operator::GreaterThan, 1);
 
@@ -52,16 +58,66 @@ impl Operator {
     }
 }
 
+#[derive(Debug)]
 pub enum OperationError {
     InvalidBinary(Value, Operator, Value),
     InvalidUnary(Operator, Value),
+    Incomparable(Value, Value),
+    DivisionByZero,
+    /// The result of applying the operator does not fit in a number.
+    Overflow(Operator),
+    /// A field read from a value that is not an object with that field.
+    MissingField(Value, String),
 }
 
+impl fmt::Display for OperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidBinary(left, op, right) => write!(
+                f,
+                "Cannot use binary operator {:?} on types {} and {}",
+                op,
+                left.type_name(),
+                right.type_name()
+            ),
+            Self::InvalidUnary(op, expr) => write!(
+                f,
+                "Cannot use unary operator {:?} on type {}",
+                op,
+                expr.type_name()
+            ),
+            Self::Incomparable(left, right) => write!(
+                f,
+                "Cannot compare values of types {} and {}",
+                left.type_name(),
+                right.type_name()
+            ),
+            Self::DivisionByZero => write!(f, "Cannot divide by zero"),
+            Self::Overflow(op) => write!(
+                f,
+                "Number overflow: the result of operator {:?} is too large",
+                op
+            ),
+            Self::MissingField(Value::Object(_), name) => {
+                write!(f, "Object has no field {}", name)
+            }
+            Self::MissingField(value, name) => write!(
+                f,
+                "Cannot access field {} on type {}",
+                name,
+                value.type_name()
+            ),
+        }
+    }
+}
+
+impl Error for OperationError {}
+
 pub enum Value {
     Number(i32),
     Boolean(bool),
-    String(String),
-    Object(Rc<RefCell<HashMap<String, Value>>>),
+    String(Rc<String>),
+    Object(Rc<RefCell<OrderedMap<String, Value>>>),
     List(Rc<RefCell<Vec<Value>>>),
     Null,
 }
@@ -71,12 +127,194 @@ impl Value {
         match &self {
             Self::Number(v) => Self::Number(*v),
             Self::Boolean(v) => Self::Boolean(*v),
-            Self::String(s) => Self::String(s.clone()),
+            Self::String(s) => Self::String(Rc::clone(s)),
             Self::Object(o) => Self::Object(Rc::clone(o)),
             Self::List(v) => Self::List(Rc::clone(v)),
             Self::Null => Self::Null,
         }
     }
+
+    /// Returns the number of bytes the value holds outside of itself, not
+    /// counting the contents of any values it holds.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Self::String(str) => str.len(),
+            Self::List(list) => list.borrow().len() * size_of::<Value>(),
+            Self::Object(obj) => obj
+                .borrow()
+                .keys()
+                .map(|key| key.len() + size_of::<Value>())
+                .sum(),
+            Self::Number(_) | Self::Boolean(_) | Self::Null => 0,
+        }
+    }
+
+    /// Returns the field with the given name, if the value is an object that
+    /// has one.
+    pub fn field(&self, name: &str) -> Result<Value, OperationError> {
+        let field = match self {
+            Self::Object(obj) => {
+                obj.borrow().get(&name.to_owned()).map(Value::copy_shallow)
+            }
+            _ => None,
+        };
+
+        field.ok_or_else(|| {
+            OperationError::MissingField(self.copy_shallow(), name.to_owned())
+        })
+    }
+
+    /// Compares two values structurally, looking into the contents of lists
+    /// and objects. Values of different types are never equal.
+    pub fn equals(&self, other: &Value) -> bool {
+        self.equals_nested(other, &mut vec![])
+    }
+
+    /// Compares two values, given the pairs of lists and objects already
+    /// being compared further up. Meeting such a pair again means the values
+    /// contain themselves in the same way, so they are taken to be equal.
+    fn equals_nested(
+        &self,
+        other: &Value,
+        comparing: &mut Vec<(*const (), *const ())>,
+    ) -> bool {
+        match (self, other) {
+            (Value::Number(num1), Value::Number(num2)) => num1 == num2,
+            (Value::Boolean(b1), Value::Boolean(b2)) => b1 == b2,
+            (Value::String(s1), Value::String(s2)) => s1 == s2,
+            (Value::Null, Value::Null) => true,
+            (Value::List(list1), Value::List(list2)) => {
+                let pair = (
+                    Rc::as_ptr(list1) as *const (),
+                    Rc::as_ptr(list2) as *const (),
+                );
+
+                if Rc::ptr_eq(list1, list2) || comparing.contains(&pair) {
+                    return true;
+                }
+
+                let (list1, list2) = (list1.borrow(), list2.borrow());
+
+                if list1.len() != list2.len() {
+                    return false;
+                }
+
+                comparing.push(pair);
+
+                let equal = list1
+                    .iter()
+                    .zip(list2.iter())
+                    .all(|(val1, val2)| val1.equals_nested(val2, comparing));
+
+                comparing.pop();
+
+                equal
+            }
+            (Value::Object(obj1), Value::Object(obj2)) => {
+                let pair = (
+                    Rc::as_ptr(obj1) as *const (),
+                    Rc::as_ptr(obj2) as *const (),
+                );
+
+                if Rc::ptr_eq(obj1, obj2) || comparing.contains(&pair) {
+                    return true;
+                }
+
+                let (obj1, obj2) = (obj1.borrow(), obj2.borrow());
+
+                if obj1.len() != obj2.len() {
+                    return false;
+                }
+
+                comparing.push(pair);
+
+                let equal = obj1.iter().all(|(key, val1)| {
+                    obj2.get(key)
+                        .is_some_and(|val2| val1.equals_nested(val2, comparing))
+                });
+
+                comparing.pop();
+
+                equal
+            }
+            _ => false,
+        }
+    }
+
+    /// Orders two values of the same type. Strings are ordered by code point,
+    /// lists item by item, and `false` comes before `true`. Objects and
+    /// values of different types cannot be ordered.
+    pub fn compare(&self, other: &Value) -> Result<Ordering, OperationError> {
+        self.compare_nested(other, &mut vec![])
+    }
+
+    /// Orders two values, given the pairs of lists already being compared
+    /// further up. Meeting such a pair again means the lists contain
+    /// themselves in the same way, so they are taken to be equal.
+    fn compare_nested(
+        &self,
+        other: &Value,
+        comparing: &mut Vec<(*const (), *const ())>,
+    ) -> Result<Ordering, OperationError> {
+        match (self, other) {
+            (Value::Number(num1), Value::Number(num2)) => Ok(num1.cmp(num2)),
+            (Value::Boolean(b1), Value::Boolean(b2)) => Ok(b1.cmp(b2)),
+            (Value::String(s1), Value::String(s2)) => Ok(s1.cmp(s2)),
+            (Value::Null, Value::Null) => Ok(Ordering::Equal),
+            (Value::List(list1), Value::List(list2)) => {
+                let pair = (
+                    Rc::as_ptr(list1) as *const (),
+                    Rc::as_ptr(list2) as *const (),
+                );
+
+                if Rc::ptr_eq(list1, list2) || comparing.contains(&pair) {
+                    return Ok(Ordering::Equal);
+                }
+
+                let (list1, list2) = (list1.borrow(), list2.borrow());
+
+                comparing.push(pair);
+
+                let mut ordering = Ok(list1.len().cmp(&list2.len()));
+
+                for (val1, val2) in list1.iter().zip(list2.iter()) {
+                    match val1.compare_nested(val2, comparing) {
+                        Ok(Ordering::Equal) => {}
+                        result => {
+                            ordering = result;
+                            break;
+                        }
+                    }
+                }
+
+                comparing.pop();
+
+                ordering
+            }
+            _ => Err(OperationError::Incomparable(
+                self.copy_shallow(),
+                other.copy_shallow(),
+            )),
+        }
+    }
+
+    /// Returns whether two values are the same list or object, rather than
+    /// merely equal ones. Other values have no identity, so they are the same
+    /// if they are equal.
+    pub fn is_same(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::List(list1), Value::List(list2)) => {
+                Rc::ptr_eq(list1, list2)
+            }
+            (Value::Object(obj1), Value::Object(obj2)) => {
+                Rc::ptr_eq(obj1, obj2)
+            }
+            (Value::List(_) | Value::Object(_), _)
+            | (_, Value::List(_) | Value::Object(_)) => false,
+            _ => self.equals(other),
+        }
+    }
+
     pub fn type_name(&self) -> &'static str {
         match *self {
             Self::Number(_) => "number",
@@ -91,7 +329,10 @@ impl Value {
     pub fn operate_unary(&self, op: Operator) -> Result<Value, OperationError> {
         if op == Operator::Minus {
             if let Value::Number(num) = self {
-                return Ok(Value::Number(-num));
+                return num
+                    .checked_neg()
+                    .map(Value::Number)
+                    .ok_or(OperationError::Overflow(op));
             }
         }
 
@@ -106,10 +347,11 @@ impl Value {
         match op {
             Operator::Plus => match (self, other) {
                 (Value::Number(num1), Value::Number(num2)) => {
-                    return Ok(Value::Number(num1 + num2));
+                    return checked_number(num1.checked_add(*num2), op);
                 }
                 (Value::String(str1), Value::String(str2)) => {
-                    return Ok(Value::String(str1.clone() + str2));
+                    let str = String::clone(str1) + str2;
+                    return Ok(Value::String(Rc::new(str)));
                 }
                 (Value::Object(obj1), Value::Object(obj2)) => {
                     let new_obj = obj1
@@ -125,7 +367,7 @@ impl Value {
             },
             Operator::Minus => match (self, other) {
                 (Value::Number(num1), Value::Number(num2)) => {
-                    return Ok(Value::Number(num1 - num2));
+                    return checked_number(num1.checked_sub(*num2), op);
                 }
                 (Value::Object(obj1), Value::Object(obj2)) => {
                     let new_obj = obj1
@@ -133,7 +375,7 @@ impl Value {
                         .iter()
                         .filter(|(k, _)| !obj2.borrow().contains_key(*k))
                         .map(|(k, v)| (k.clone(), v.copy_shallow()))
-                        .collect::<HashMap<_, _>>();
+                        .collect::<OrderedMap<_, _>>();
 
                     return Ok(Value::Object(Rc::new(RefCell::new(new_obj))));
                 }
@@ -143,42 +385,27 @@ impl Value {
                 if let (Value::Number(num1), Value::Number(num2)) =
                     (self, other)
                 {
-                    return Ok(Value::Number(num1 * num2));
+                    return checked_number(num1.checked_mul(*num2), op);
                 }
             }
             Operator::Slash => {
                 if let (Value::Number(num1), Value::Number(num2)) =
                     (self, other)
                 {
-                    return Ok(Value::Number(num1 / num2));
+                    if *num2 == 0 {
+                        return Err(OperationError::DivisionByZero);
+                    }
+
+                    return checked_number(num1.checked_div(*num2), op);
                 }
             }
-            Operator::Equals => match (self, other) {
-                (Value::Number(num1), Value::Number(num2)) => {
-                    return Ok(Value::Boolean(*num1 == *num2));
-                }
-                (Value::Boolean(b1), Value::Boolean(b2)) => {
-                    return Ok(Value::Boolean(*b1 == *b2));
-                }
-                (Value::String(s1), Value::String(s2)) => {
-                    return Ok(Value::Boolean(s1 == s2));
-                }
-                (Value::Null, Value::Null) => return Ok(Value::Boolean(true)),
-                _ => {}
-            },
+            // Values of any two types can be compared for equality.
+            Operator::Equals => return Ok(Value::Boolean(self.equals(other))),
             Operator::LessThan => {
-                if let (Value::Number(num1), Value::Number(num2)) =
-                    (self, other)
-                {
-                    return Ok(Value::Boolean(num1 < num2));
-                }
+                return Ok(Value::Boolean(self.compare(other)?.is_lt()));
             }
             Operator::GreaterThan => {
-                if let (Value::Number(num1), Value::Number(num2)) =
-                    (self, other)
-                {
-                    return Ok(Value::Boolean(num1 > num2));
-                }
+                return Ok(Value::Boolean(self.compare(other)?.is_gt()));
             }
         }
 
@@ -190,43 +417,236 @@ impl Value {
     }
 }
 
+/// Turns the result of checked arithmetic into a number, or an overflow
+/// error if there was none.
+fn checked_number(
+    result: Option<i32>,
+    op: Operator,
+) -> Result<Value, OperationError> {
+    result
+        .map(Value::Number)
+        .ok_or(OperationError::Overflow(op))
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_nested(f, &mut vec![], false)
+    }
+}
+
+/// Writes the value as it is displayed, but with its strings quoted. It is
+/// not derived, as lists and objects can hold themselves.
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_nested(f, &mut vec![], true)
+    }
+}
+
+impl Value {
+    /// Writes the value, given the addresses of the lists and objects it is
+    /// nested in. A value nested inside itself is written as `<cycle>`, while
+    /// one that is merely shared is written in full each time. Strings are
+    /// quoted if `debug` is set.
+    fn fmt_nested(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        parents: &mut Vec<*const ()>,
+        debug: bool,
+    ) -> fmt::Result {
+        let address = match self {
+            Value::List(list) => Rc::as_ptr(list) as *const (),
+            Value::Object(obj) => Rc::as_ptr(obj) as *const (),
+            _ => std::ptr::null(),
+        };
+
+        if parents.contains(&address) {
+            return write!(f, "<cycle>");
+        }
+
         match self {
             Value::Number(num) => write!(f, "{}", num),
             Value::Boolean(bool) => write!(f, "{}", bool),
+            Value::String(str) if debug => write!(f, "{:?}", str),
             Value::String(str) => write!(f, "{}", str),
             Value::Null => write!(f, "null"),
             Value::List(list) => {
-                if list.borrow().is_empty() {
+                let list = list.borrow();
+
+                if list.is_empty() {
                     return write!(f, "{{}}");
                 }
 
-                let values = list
-                    .borrow()
-                    .iter()
-                    .map(Value::to_string)
-                    .collect::<Vec<_>>()
-                    .join(", ");
+                parents.push(address);
+
+                write!(f, "[ ")?;
 
-                write!(f, "[ {} ]", values)
+                for (index, value) in list.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    value.fmt_nested(f, parents, debug)?;
+                }
+
+                parents.pop();
+
+                write!(f, " ]")
             }
             Value::Object(obj) => {
-                if obj.borrow().is_empty() {
+                let obj = obj.borrow();
+
+                if obj.is_empty() {
                     return write!(f, "{{}}");
                 }
 
-                let fields = obj
-                    .borrow()
-                    .iter()
-                    .map(|(key, value)| {
-                        key.to_owned() + ": " + &value.to_string()
-                    })
-                    .collect::<Vec<_>>()
-                    .join(", ");
+                parents.push(address);
+
+                write!(f, "{{ ")?;
+
+                for (index, (key, value)) in obj.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}: ", key)?;
+                    value.fmt_nested(f, parents, debug)?;
+                }
+
+                parents.pop();
+
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    fn object(fields: Vec<(&str, Value)>) -> Value {
+        let fields = fields
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect();
+
+        Value::Object(Rc::new(RefCell::new(fields)))
+    }
+
+    fn string(str: &str) -> Value {
+        Value::String(Rc::new(str.to_owned()))
+    }
+
+    #[test]
+    fn equals_compares_contents() {
+        let a = list(vec![Value::Number(1), object(vec![("x", Value::Null)])]);
+        let b = list(vec![Value::Number(1), object(vec![("x", Value::Null)])]);
+
+        assert!(a.equals(&b));
+        assert!(!a.is_same(&b));
+        assert!(a.is_same(&a.copy_shallow()));
+        assert!(!a.equals(&list(vec![Value::Number(1)])));
+    }
+
+    #[test]
+    fn equals_ignores_field_order() {
+        let a = object(vec![("x", Value::Number(1)), ("y", string("a"))]);
+        let b = object(vec![("y", string("a")), ("x", Value::Number(1))]);
+
+        assert!(a.equals(&b));
+    }
+
+    #[test]
+    fn values_of_different_types_are_not_equal() {
+        assert!(!Value::Number(1).equals(&string("1")));
+        assert!(!Value::Null.equals(&Value::Boolean(false)));
+        assert!(!list(vec![]).equals(&object(vec![])));
+
+        let equal = Value::Number(0).operate(&Value::Null, Operator::Equals);
+        assert!(matches!(equal, Ok(Value::Boolean(false))));
+    }
 
-                write!(f, "{{ {} }}", fields)
+    #[test]
+    fn equals_handles_cycles() {
+        let a = list(vec![Value::Number(1)]);
+        let b = list(vec![Value::Number(1)]);
+
+        for value in [&a, &b] {
+            if let Value::List(items) = value {
+                items.borrow_mut().push(value.copy_shallow());
+            }
+        }
+
+        assert!(a.equals(&b));
+
+        // The cycles have to be broken for the lists to be freed.
+        for value in [&a, &b] {
+            if let Value::List(items) = value {
+                items.borrow_mut().clear();
             }
         }
     }
+
+    #[test]
+    fn compare_orders_values_of_the_same_type() {
+        let cmp = |a: Value, b: Value| a.compare(&b).unwrap();
+
+        assert_eq!(cmp(Value::Number(2), Value::Number(10)), Ordering::Less);
+        assert_eq!(cmp(string("b"), string("ab")), Ordering::Greater);
+        assert_eq!(
+            cmp(Value::Boolean(false), Value::Boolean(true)),
+            Ordering::Less
+        );
+        assert_eq!(cmp(Value::Null, Value::Null), Ordering::Equal);
+        assert_eq!(
+            cmp(
+                list(vec![Value::Number(1), Value::Number(2)]),
+                list(vec![Value::Number(1), Value::Number(2), Value::Null])
+            ),
+            Ordering::Less
+        );
+        assert_eq!(
+            cmp(
+                list(vec![Value::Number(2)]),
+                list(vec![Value::Number(1), Value::Number(5)])
+            ),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn compare_handles_cycles() {
+        let a = list(vec![Value::Number(1)]);
+        let b = list(vec![Value::Number(1)]);
+
+        for value in [&a, &b] {
+            if let Value::List(items) = value {
+                items.borrow_mut().push(value.copy_shallow());
+            }
+        }
+
+        assert_eq!(a.compare(&b).unwrap(), Ordering::Equal);
+
+        for value in [&a, &b] {
+            if let Value::List(items) = value {
+                items.borrow_mut().clear();
+            }
+        }
+    }
+
+    #[test]
+    fn compare_rejects_objects_and_mixed_types() {
+        assert!(matches!(
+            Value::Number(1).compare(&string("1")),
+            Err(OperationError::Incomparable(..))
+        ));
+        assert!(matches!(
+            object(vec![]).compare(&object(vec![])),
+            Err(OperationError::Incomparable(..))
+        ));
+    }
 }
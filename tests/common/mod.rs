@@ -0,0 +1,120 @@
+//! Helpers for running programs in the tests, whichever backend runs them.
+
+#![allow(dead_code)]
+
+use rlang::interpreter::Interpreter;
+use rlang::lexer::Lexer;
+use rlang::optimizer::Optimizer;
+use rlang::parser::Parser;
+use rlang::prelude;
+use rlang::syntax::Decl;
+use rlang::RuntimeError;
+use rlang::SharedBuffer;
+
+/// A way of running a program.
+#[derive(Clone, Copy, Debug)]
+pub struct Mode {
+    /// Whether the closure-compilation backend runs the program.
+    pub compile: bool,
+    /// Whether the program is optimized first.
+    pub optimize: bool,
+}
+
+pub const INTERPRETED: Mode = Mode {
+    compile: false,
+    optimize: false,
+};
+
+pub const COMPILED: Mode = Mode {
+    compile: true,
+    optimize: false,
+};
+
+/// Every way of running a program, the plain interpreter first.
+pub const MODES: [Mode; 4] = [
+    INTERPRETED,
+    Mode {
+        compile: false,
+        optimize: true,
+    },
+    COMPILED,
+    Mode {
+        compile: true,
+        optimize: true,
+    },
+];
+
+/// Runs the program with the prelude, returning what it printed followed by
+/// the error it stopped with, if any.
+pub fn run(source: &str, mode: Mode) -> String {
+    run_with(source, mode, |_| {})
+}
+
+/// Runs the program like [`run`], after setting up the interpreter.
+pub fn run_with(
+    source: &str,
+    mode: Mode,
+    setup: impl FnOnce(&mut Interpreter),
+) -> String {
+    let stdout = SharedBuffer::new();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_stdout(stdout.clone());
+    setup(&mut interpreter);
+
+    let result = execute(&mut interpreter, source, mode);
+    let mut output = stdout.take();
+
+    if let Err(err) = result {
+        output.push_str(&format!("error: {}\n", err));
+    }
+
+    output
+}
+
+/// Runs the program with the prelude on the interpreter as it is set up.
+pub fn execute(
+    interpreter: &mut Interpreter,
+    source: &str,
+    mode: Mode,
+) -> Result<(), RuntimeError> {
+    let tokens = Lexer::new(source.to_owned()).scan_tokens().unwrap();
+    let decls = Parser::new(tokens).parse().unwrap();
+
+    execute_decls(interpreter, decls, mode)
+}
+
+/// Runs the declarations with the prelude like [`execute`].
+pub fn execute_decls(
+    interpreter: &mut Interpreter,
+    decls: Vec<Decl>,
+    mode: Mode,
+) -> Result<(), RuntimeError> {
+    let decls = prelude::decls()
+        .into_iter()
+        .chain(decls)
+        .collect::<Vec<_>>();
+
+    let decls = if mode.optimize {
+        Optimizer::new(&decls).optimize(decls)
+    } else {
+        decls
+    };
+
+    if mode.compile {
+        interpreter.interpret_compiled(decls, vec![])
+    } else {
+        interpreter.interpret(decls, vec![])
+    }
+}
+
+/// Returns the output of the program, after checking that it is the same
+/// whichever way the program is run.
+pub fn run_all_ways(source: &str) -> String {
+    let expected = run(source, INTERPRETED);
+
+    for mode in &MODES[1..] {
+        assert_eq!(run(source, *mode), expected, "{:?}", mode);
+    }
+
+    expected
+}
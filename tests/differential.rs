@@ -0,0 +1,488 @@
+//! Runs the same programs in every way a program can be run, checking that
+//! each prints the same output and stops with the same error.
+
+mod common;
+
+use std::io::Cursor;
+use std::rc::Rc;
+
+use common::*;
+use rlang::interpreter::Interpreter;
+use rlang::RuntimeError;
+use rlang::SharedBuffer;
+use rlang::Value;
+
+#[test]
+fn arithmetic_and_strings() {
+    let output = run_all_ways(
+        r#"
+        const BASE = 10;
+        let x = BASE + 2 * 3;
+        x = x - 1;
+        print(x);
+        print(-x / 4);
+        print("a" + "b" + tostring(BASE));
+        print(1 < 2 == true);
+        "#,
+    );
+
+    assert_eq!(output, "15\n-3\nab10\ntrue\n");
+}
+
+#[test]
+fn functions_and_loops() {
+    let output = run_all_ways(
+        r#"
+        fn fib(n) {
+            if n < 2 {
+                return n;
+            }
+
+            return fib(n - 1) + fib(n - 2);
+        }
+
+        fn main() {
+            let i = 0;
+            let total = 0;
+
+            while i < 5 {
+                total = total + i;
+                i = i + 1;
+            }
+
+            print(total);
+            print(fib(15));
+        }
+        "#,
+    );
+
+    assert_eq!(output, "10\n610\n");
+}
+
+#[test]
+fn compiler_rejects_variables_of_callers() {
+    let source = r#"
+        fn show() {
+            print(x);
+        }
+
+        fn main() {
+            let x = 1;
+            show();
+        }
+        "#;
+
+    assert_eq!(run(source, INTERPRETED), "1\n");
+    assert_eq!(
+        run(source, COMPILED),
+        "error: x in show may refer to a variable of a function calling it, \
+         which compiled programs cannot see\n"
+    );
+}
+
+#[test]
+fn tail_calls_keep_variables_callees_can_see() {
+    let source = r#"
+        fn f(n) {
+            if n == 0 {
+                return x;
+            }
+
+            let x = n;
+            return f(n - 1);
+        }
+
+        print(f(2));
+        "#;
+
+    assert_eq!(run(source, INTERPRETED), "1\n");
+}
+
+#[test]
+fn collector_frees_cycles_left_by_the_program() {
+    let output = run_all_ways(
+        r#"
+        fn make_cycle() {
+            let list = [1];
+            append(list, list);
+        }
+
+        make_cycle();
+        make_cycle();
+        print(gc());
+        print(gc());
+        "#,
+    );
+
+    assert_eq!(output, "2\n0\n");
+}
+
+#[test]
+fn caught_values_are_only_tracked_once() {
+    let output = run_all_ways(
+        r#"
+        let inner = [1];
+        let outer = [inner];
+        inner = null;
+
+        try {
+            throw outer;
+        } catch (e) {
+            print(e);
+        }
+
+        print(gc());
+        "#,
+    );
+
+    assert_eq!(output, "[ [ 1 ] ]\n0\n");
+}
+
+#[test]
+fn values_that_hold_themselves_print_a_marker() {
+    let output = run_all_ways(
+        r#"
+        let list = [1];
+        append(list, list);
+        print(list);
+
+        let obj = { name: "a" };
+        let shared = [obj, obj];
+        print(shared);
+        print(tostring([shared, list]));
+
+        set(list, 1, null);
+        "#,
+    );
+
+    assert_eq!(
+        output,
+        "[ 1, <cycle> ]\n\
+         [ { name: a }, { name: a } ]\n\
+         [ [ { name: a }, { name: a } ], [ 1, <cycle> ] ]\n"
+    );
+}
+
+#[test]
+fn objects_keep_their_fields_in_insertion_order() {
+    let output = run_all_ways(
+        r#"
+        let obj = { zebra: 1, apple: 2, mango: 3 };
+        print(obj);
+        print(obj + { apple: 4, banana: 5 });
+        "#,
+    );
+
+    assert_eq!(
+        output,
+        "{ zebra: 1, apple: 2, mango: 3 }\n\
+         { zebra: 1, apple: 4, mango: 3, banana: 5 }\n"
+    );
+}
+
+#[test]
+fn equality_compares_contents_and_same_compares_identity() {
+    let output = run_all_ways(
+        r#"
+        let a = [1, { x: "y" }];
+        let b = [1, { x: "y" }];
+        print(a == b);
+        print(same(a, b));
+        print(same(a, a));
+        print({ x: 1, y: 2 } == { y: 2, x: 1 });
+        print([1] == [1, 2]);
+        print(1 == "1");
+        print(null == false);
+        "#,
+    );
+
+    assert_eq!(output, "true\nfalse\ntrue\ntrue\nfalse\nfalse\nfalse\n");
+}
+
+#[test]
+fn strings_lists_booleans_and_null_are_ordered() {
+    let output = run_all_ways(
+        r#"
+        print("apple" < "banana");
+        print("b" > "ab");
+        print([1, 2] < [1, 2, 0]);
+        print(compare(false, true));
+        print(compare(null, null));
+        print(compare([2], [1, 5]));
+        print(sort_by(["pear", "fig", "apple"], "compare"));
+
+        try {
+            compare(1, "1");
+        } catch (e) {
+            print(e.kind);
+        }
+        "#,
+    );
+
+    assert_eq!(
+        output,
+        "true\ntrue\ntrue\n-1\n0\n1\n[ apple, fig, pear ]\nOperationError\n"
+    );
+}
+
+#[test]
+fn builtins_see_the_caller() {
+    let source = r#"
+        let x = "global";
+
+        fn inner() {
+            let x = "local";
+            peek();
+        }
+
+        peek();
+        inner();
+        "#;
+
+    fn setup(interpreter: &mut Interpreter) {
+        interpreter.define_fn("peek", 0, |ctx, _| {
+            let span = ctx
+                .span()
+                .ok_or_else(|| RuntimeError::HostError("no span".to_owned()))?;
+            let found = ctx.lookup("x").unwrap_or(Value::Null);
+            let missing = ctx.lookup("y").unwrap_or(Value::Null);
+            let line = format!("{} {} at {}", found, missing, span);
+
+            ctx.call("print", vec![Value::String(Rc::new(line))])
+        });
+    }
+
+    for mode in MODES {
+        assert_eq!(
+            run_with(source, mode, setup),
+            "global null at line 9, column 9\n\
+             local null at line 6, column 13\n",
+            "{:?}",
+            mode
+        );
+    }
+}
+
+#[test]
+fn programs_use_the_streams_they_are_given() {
+    let source = r#"
+        let name = prompt("Name? ");
+        let age = parseint(prompt("Age? "));
+        print(name + " is " + tostring(age + 1) + " next year");
+        eprint("done");
+        "#;
+
+    for mode in MODES {
+        let stderr = SharedBuffer::new();
+        let output = run_with(source, mode, |interpreter| {
+            interpreter.set_stdin(Cursor::new("  Ada \n41\n"));
+            interpreter.set_stderr(stderr.clone());
+        });
+
+        assert_eq!(output, "Name? Age? Ada is 42 next year\n", "{:?}", mode);
+        assert_eq!(stderr.take(), "done\n", "{:?}", mode);
+    }
+}
+
+#[test]
+fn exceptions() {
+    let output = run_all_ways(
+        r#"
+        fn fail(value) {
+            throw value;
+        }
+
+        fn safe() {
+            try {
+                return fail("inner");
+            } finally {
+                print("cleanup");
+            }
+        }
+
+        try {
+            get([], 1);
+        } catch (e) {
+            print(e.kind);
+            print(e.message);
+            print(e.stack);
+        }
+
+        try {
+            safe();
+        } catch (e) {
+            print(e);
+        }
+
+        try {
+            let obj = { a: 1 };
+            let x = obj.b;
+        } catch (e) {
+            print(e.kind);
+        }
+
+        fail({ code: 3 });
+        "#,
+    );
+
+    assert_eq!(
+        output,
+        "IndexOutOfBounds\n\
+         Index out of bounds: used index 1 on a list of length 0\n\
+         [ <script> ]\n\
+         cleanup\ninner\nOperationError\n\
+         error: Uncaught exception: { code: 3 }\n\
+         Traceback, most recent call last:\n    \
+         in <script> at line 35, column 9\n    \
+         in fail at line 3, column 13\n"
+    );
+}
+
+#[test]
+fn tracebacks() {
+    let output = run_all_ways(
+        r#"
+        fn half(list) {
+            return get(list, 0) / 2;
+        }
+
+        fn main() {
+            print(half([4]));
+            print(half(null));
+        }
+        "#,
+    );
+
+    assert_eq!(
+        output,
+        "2\nerror: Expected type list, got null\n\
+         Traceback, most recent call last:\n    \
+         in main at line 8, column 19\n    \
+         in half at line 3, column 20\n"
+    );
+}
+
+#[test]
+fn wrong_arg_count_is_raised_at_the_call() {
+    let output = run_all_ways(
+        r#"
+        fn pair(a, b) {
+            if a > 0 {
+                return pair(a - 1);
+            }
+
+            return b;
+        }
+
+        fn main() {
+            print(pair(2, 1));
+        }
+        "#,
+    );
+
+    assert_eq!(
+        output,
+        "error: Expected 2 args, got 1\n\
+         Traceback, most recent call last:\n    \
+         in main at line 11, column 19\n    \
+         in pair at line 4, column 24\n"
+    );
+}
+
+#[test]
+fn deep_tracebacks_show_the_innermost_calls() {
+    let output = run_all_ways(
+        r#"
+        fn down(n) {
+            if n == 0 {
+                return missing;
+            }
+
+            return 1 + down(n - 1);
+        }
+
+        down(12);
+        "#,
+    );
+
+    let mut expected = "error: Unknown identifier: missing\n\
+                        Traceback, most recent call last:\n    \
+                        ... 4 more calls\n"
+        .to_owned();
+    for _ in 0..9 {
+        expected.push_str("    in down at line 7, column 24\n");
+    }
+    expected.push_str("    in down at line 4, column 24\n");
+
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn constant_assignment() {
+    let output = run_all_ways(
+        r#"
+        const LIMIT = 3;
+
+        fn raise() {
+            LIMIT = 4;
+        }
+
+        try {
+            raise();
+        } catch (e) {
+            print(e.kind);
+        }
+
+        print(LIMIT);
+        "#,
+    );
+
+    assert_eq!(output, "ConstantAssignment\n3\n");
+}
+
+#[test]
+fn assigning_an_undeclared_variable() {
+    let output = run_all_ways(
+        r#"
+        fn assign() {
+            missing = 1;
+        }
+
+        try {
+            assign();
+        } catch (e) {
+            print(e.message);
+        }
+
+        undeclared = 2;
+        "#,
+    );
+
+    assert_eq!(
+        output,
+        "Unknown identifier: missing\n\
+         error: Unknown identifier: undeclared\n\
+         Traceback, most recent call last:\n    \
+         in <script> at line 12, column 9\n"
+    );
+}
+
+#[test]
+fn prelude_functions_run_in_every_mode() {
+    let output = run_all_ways(
+        r#"
+        fn repeat(value, count) {
+            return [value, count];
+        }
+
+        let items = range(1, 5);
+        print(items);
+        print(sum(items));
+        print(max_of(items));
+        print(contains(items, 3));
+        print(repeat("a", 2));
+        "#,
+    );
+
+    assert_eq!(output, "[ 1, 2, 3, 4 ]\n10\n4\ntrue\n[ a, 2 ]\n");
+}
@@ -0,0 +1,84 @@
+//! Runs invalid programs through the command line, checking that each stops
+//! with an error message and a failing exit code rather than a panic.
+
+use std::fs;
+use std::process::Command;
+
+/// Runs the program with the interpreter and then the compiler, returning
+/// the error each printed along with its exit code.
+fn run_invalid(name: &str, source: &str) -> Vec<(String, Option<i32>)> {
+    let dir = std::env::temp_dir().join(format!("rlang-errors-{}", name));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.rl"), source).unwrap();
+
+    let results = [&[][..], &["-c"][..]]
+        .iter()
+        .map(|flags| {
+            let output = Command::new(env!("CARGO_BIN_EXE_rlang"))
+                .args(*flags)
+                .arg("main.rl")
+                .current_dir(&dir)
+                .output()
+                .unwrap();
+
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            let first_line = stderr.lines().next().unwrap_or("").to_owned();
+
+            (first_line, output.status.code())
+        })
+        .collect();
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    results
+}
+
+fn assert_fails_with(name: &str, source: &str, message: &str) {
+    for (error, code) in run_invalid(name, source) {
+        assert_eq!(error, message);
+        assert_eq!(code, Some(1));
+    }
+}
+
+#[test]
+fn parse_errors_say_where_they_are() {
+    assert_fails_with(
+        "missing-semicolon",
+        "fn f() {\n    return 1\n}\n",
+        "Parse error: Expected Semicolon, got RightBrace at line 3, column 1",
+    );
+    assert_fails_with(
+        "end-of-file",
+        "fn f(",
+        "Parse error: Expected Identifer, got EndOfFile at line 1, column 6",
+    );
+    assert_fails_with(
+        "invalid-char",
+        "let x = 1 @ 2;",
+        "Parse error: Invalid character '@' at line 1, column 11",
+    );
+    assert_fails_with(
+        "large-number",
+        "let x = 99999999999;",
+        "Parse error: Number 99999999999 at line 1, column 9 is too large",
+    );
+    assert_fails_with(
+        "unterminated-string",
+        "let x = \"abc",
+        "Parse error: String starting at line 1, column 9 is never closed",
+    );
+}
+
+#[test]
+fn runtime_errors_stop_the_program() {
+    assert_fails_with(
+        "condition",
+        "if 1 { print(1); }",
+        "Runtime error: Expected a boolean condition, got number",
+    );
+    assert_fails_with(
+        "parseint",
+        "print(parseint(\"x\"));",
+        "Runtime error: Invalid number: \"x\" is not a number",
+    );
+}
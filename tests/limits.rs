@@ -0,0 +1,341 @@
+//! Checks the limits a host can put on the programs it runs.
+
+mod common;
+
+use common::*;
+use rlang::interpreter::Interpreter;
+use rlang::RuntimeError;
+use rlang::SharedBuffer;
+
+const RECURSION: &str = r#"
+    fn down(n) {
+        return 1 + down(n + 1);
+    }
+
+    down(0);
+    "#;
+
+#[test]
+fn calls_stop_at_the_maximum_depth() {
+    for mode in MODES {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_depth(50);
+
+        let err = execute(&mut interpreter, RECURSION, mode).unwrap_err();
+        assert!(
+            matches!(err.root(), RuntimeError::StackOverflow(50)),
+            "{:?}: {}",
+            mode,
+            err
+        );
+    }
+}
+
+#[test]
+fn compiled_calls_stop_before_the_stack_runs_out() {
+    // The limit is checked against the stack of the thread running the
+    // program, which is given plenty to spare.
+    let err = std::thread::Builder::new()
+        .stack_size(64 * 1024 * 1024)
+        .spawn(|| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_max_depth(usize::MAX);
+            interpreter.set_stack_size(1024 * 1024);
+
+            execute(&mut interpreter, RECURSION, COMPILED)
+                .unwrap_err()
+                .to_string()
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert!(
+        err.starts_with(
+            "Stack overflow: nested calls used more than the 1048576 bytes \
+             of native stack they were allowed"
+        ),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn interpreted_calls_nest_deeper_than_the_native_stack() {
+    let source = r#"
+        fn depth(n) {
+            if n == 0 {
+                return 0;
+            }
+
+            return 1 + depth(n - 1);
+        }
+
+        print(depth(100000));
+        "#;
+
+    let output = run_with(source, INTERPRETED, |interpreter| {
+        interpreter.set_max_depth(200000);
+    });
+    assert_eq!(output, "100000\n");
+}
+
+/// Runs the program with the given fuel, returning what it printed, the
+/// error it stopped with, if any, and the fuel it had left.
+fn run_with_fuel(source: &str, mode: Mode, fuel: u64) -> (String, Option<u64>) {
+    let stdout = SharedBuffer::new();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_stdout(stdout.clone());
+    interpreter.set_fuel(fuel);
+
+    let result = execute(&mut interpreter, source, mode);
+    let mut output = stdout.take();
+
+    if let Err(err) = result {
+        output.push_str(&format!("error: {}\n", err.root()));
+    }
+
+    (output, interpreter.remaining_fuel())
+}
+
+#[test]
+fn both_backends_use_the_same_fuel() {
+    let source = r#"
+        fn check(i) {
+            print("check " + tostring(i));
+            return i < 3;
+        }
+
+        fn count(n) {
+            if n == 0 {
+                return 0;
+            }
+
+            return 1 + count(n - 1);
+        }
+
+        let i = 0;
+
+        while check(i) {
+            try {
+                print(count(i));
+            } catch (e) {
+                print(e.kind);
+            }
+
+            i = i + 1;
+        }
+
+        print(len(map([1, 2, 3], "count")));
+        "#;
+
+    // The optimizer can remove statements, so each backend is only compared
+    // with the other running the same program.
+    for [interpreted, compiled] in [[MODES[0], MODES[2]], [MODES[1], MODES[3]]]
+    {
+        let mut finished = false;
+
+        for fuel in 0..1000 {
+            let expected = run_with_fuel(source, interpreted, fuel);
+            assert_eq!(
+                run_with_fuel(source, compiled, fuel),
+                expected,
+                "{:?} with {} fuel",
+                compiled,
+                fuel
+            );
+
+            if !expected.0.contains("error") {
+                finished = true;
+                break;
+            }
+        }
+
+        assert!(finished, "{:?} never finished", interpreted);
+    }
+}
+
+/// Runs the program in every way, returning the error each run stopped with.
+fn errors_with_max_memory(source: &str, max_memory: usize) -> Vec<String> {
+    MODES
+        .iter()
+        .map(|&mode| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_stdout(SharedBuffer::new());
+            interpreter.set_max_memory(max_memory);
+
+            match execute(&mut interpreter, source, mode) {
+                Ok(()) => "none".to_owned(),
+                Err(err) => err.root().kind().to_owned(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn memory_limit_stops_growing_lists_and_strings() {
+    let lists = r#"
+        let list = [];
+
+        while true {
+            append(list, 1);
+        }
+        "#;
+    let strings = r#"
+        let str = "a";
+
+        while true {
+            str = str + str;
+        }
+        "#;
+
+    for source in [lists, strings] {
+        assert_eq!(errors_with_max_memory(source, 100_000), ["MemoryLimit"; 4]);
+    }
+}
+
+#[test]
+fn memory_limit_counts_string_literals() {
+    let source = r#"
+        let str = "a string literal longer than the limit";
+        "#;
+
+    assert_eq!(errors_with_max_memory(source, 16), ["MemoryLimit"; 4]);
+}
+
+#[test]
+fn freed_memory_is_given_back() {
+    let source = r#"
+        let i = 0;
+
+        while i < 1000 {
+            let list = [i, i + 1, i + 2, tostring(i) + " squared"];
+            i = i + 1;
+        }
+        "#;
+
+    assert_eq!(errors_with_max_memory(source, 1000), ["none"; 4]);
+}
+
+#[test]
+fn memory_freed_by_the_collector_is_given_back() {
+    let source = r#"
+        fn cycles(collect) {
+            let i = 0;
+
+            while i < 1000 {
+                let list = [i, i + 1, i + 2];
+                append(list, list);
+
+                if collect {
+                    gc();
+                }
+
+                i = i + 1;
+            }
+        }
+
+        cycles(COLLECT);
+        "#;
+
+    let collected = source.replace("COLLECT", "true");
+    let kept = source.replace("COLLECT", "false");
+
+    assert_eq!(errors_with_max_memory(&collected, 1000), ["none"; 4]);
+    assert_eq!(errors_with_max_memory(&kept, 1000), ["MemoryLimit"; 4]);
+}
+
+#[test]
+fn memory_in_use_counts_what_the_program_holds() {
+    let mut interpreter = Interpreter::new();
+    execute(&mut interpreter, "", INTERPRETED).unwrap();
+    let before = interpreter.memory_in_use();
+
+    let source = r#"
+        let kept = [1, 2, 3];
+
+        fn discard() {
+            let discarded = [1, 2, 3, 4, 5, 6, 7, 8];
+        }
+
+        discard();
+        "#;
+    execute(&mut interpreter, source, INTERPRETED).unwrap();
+
+    assert_eq!(
+        interpreter.memory_in_use() - before,
+        3 * size_of::<rlang::value::Value>()
+    );
+}
+
+#[test]
+fn interrupts_stop_the_program_from_another_thread() {
+    let source = r#"
+        fn spin() {
+            while true {
+                try {
+                    let i = 0;
+                } catch (e) {
+                    print(e);
+                }
+            }
+        }
+
+        spin();
+        "#;
+
+    for mode in MODES {
+        let mut interpreter = Interpreter::new();
+        let handle = interpreter.interrupt_handle();
+
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.interrupt();
+        });
+
+        let err = execute(&mut interpreter, source, mode).unwrap_err();
+        interrupter.join().unwrap();
+
+        assert!(
+            matches!(err.root(), RuntimeError::Interrupted),
+            "{:?}: {}",
+            mode,
+            err
+        );
+        assert!(
+            matches!(
+                &err,
+                RuntimeError::Traced(_, frames)
+                    if frames.iter().any(|frame| frame.name == "spin")
+            ),
+            "{:?}: {}",
+            mode,
+            err
+        );
+    }
+}
+
+#[test]
+fn interrupts_only_stop_one_run() {
+    let source = "fn greet() { print(1); } greet();";
+
+    for mode in MODES {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_stdout(SharedBuffer::new());
+        let handle = interpreter.interrupt_handle();
+
+        handle.interrupt();
+        let err = execute(&mut interpreter, source, mode).unwrap_err();
+        assert!(
+            matches!(err.root(), RuntimeError::Interrupted),
+            "{:?}",
+            mode
+        );
+
+        assert!(execute(&mut interpreter, source, mode).is_ok());
+
+        handle.interrupt();
+        handle.reset();
+        assert!(execute(&mut interpreter, source, mode).is_ok());
+    }
+}
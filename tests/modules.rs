@@ -0,0 +1,185 @@
+//! Runs programs that import modules, checking that functions are found by
+//! the names the code calling them knows them by, whichever backend runs
+//! them.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::*;
+use rlang::interpreter::Interpreter;
+use rlang::lexer::Lexer;
+use rlang::modules::ModuleLoader;
+use rlang::parser::Parser;
+use rlang::RuntimeError;
+use rlang::SharedBuffer;
+use rlang::Value;
+
+/// Writes the files into a new directory for the test, then runs the program
+/// in its `main.rl` each way, checking that the output is the same.
+fn run_program(test: &str, files: &[(&str, &str)]) -> String {
+    let dir = std::env::temp_dir().join(format!(
+        "rlang-modules-{}-{}",
+        std::process::id(),
+        test
+    ));
+
+    for (name, source) in files {
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(name), source).unwrap();
+    }
+
+    let outputs = MODES
+        .iter()
+        .map(|mode| (mode, run_in(&dir.join("main.rl"), *mode)))
+        .collect::<Vec<_>>();
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    let (_, expected) = &outputs[0];
+
+    for (mode, output) in &outputs[1..] {
+        assert_eq!(output, expected, "{:?}", mode);
+    }
+
+    expected.clone()
+}
+
+/// Runs the program at the path with the prelude and an `apply` builtin,
+/// which calls the function it is given the name of like `map` does.
+fn run_in(path: &Path, mode: Mode) -> String {
+    let stdout = SharedBuffer::new();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_stdout(stdout.clone());
+
+    interpreter.define_fn("apply", 2, |ctx, mut args| {
+        let arg = args.pop().unwrap();
+
+        match &args[0] {
+            Value::String(name) => ctx.call(name, vec![arg]),
+            value => Err(RuntimeError::InvalidArgumentType(
+                "string".into(),
+                value.type_name().into(),
+            )),
+        }
+    });
+
+    let source = fs::read_to_string(path).unwrap();
+    let tokens = Lexer::new(source).scan_tokens().unwrap();
+    let decls = Parser::new(tokens).parse().unwrap();
+    let decls = ModuleLoader::new().load(path, decls).unwrap();
+
+    let result = execute_decls(&mut interpreter, decls, mode);
+    let mut output = stdout.take();
+
+    if let Err(err) = result {
+        output.push_str(&format!("error: {}\n", err));
+    }
+
+    output
+}
+
+const UTIL: &str = r#"
+pub const SCALE = 10;
+
+fn helper(x) {
+    return x;
+}
+
+pub fn scale(x) {
+    return helper(x) * SCALE;
+}
+
+pub fn scale_all(xs) {
+    return map(xs, "scale");
+}
+
+pub fn apply_own(x) {
+    return apply("sc" + "ale", x);
+}
+"#;
+
+#[test]
+fn builtins_call_functions_by_the_names_modules_know() {
+    let output = run_program(
+        "callbacks",
+        &[
+            ("util.rl", UTIL),
+            (
+                "main.rl",
+                r#"
+import "util.rl" as u;
+
+fn twice(x) {
+    return x * 2;
+}
+
+print(map([1, 2], "u.scale"));
+print(u.scale_all([3]));
+print(apply("u.scale", 4));
+print(apply("twice", 4));
+print(u.apply_own(5));
+let name = "u." + "scale";
+print(apply(name, 6));
+"#,
+            ),
+        ],
+    );
+
+    assert_eq!(output, "[ 10, 20 ]\n[ 30 ]\n40\n8\n50\n60\n");
+}
+
+#[test]
+fn builtins_cannot_call_private_functions() {
+    let output = run_program(
+        "private",
+        &[
+            ("util.rl", UTIL),
+            (
+                "main.rl",
+                r#"
+import "util.rl" as u;
+
+print(apply("u.helper", 1));
+"#,
+            ),
+        ],
+    );
+
+    assert_eq!(
+        output,
+        "error: Unknown identifier: u.helper\n\
+        Traceback, most recent call last:\n    \
+            in <script> at line 4, column 7\n"
+    );
+}
+
+#[test]
+fn fields_of_variables_are_not_module_members() {
+    let output = run_program(
+        "fields",
+        &[
+            ("util.rl", UTIL),
+            (
+                "main.rl",
+                r#"
+import "util.rl" as u;
+
+try {
+    throw 1;
+} catch (e) {
+    e.x = 2;
+}
+"#,
+            ),
+        ],
+    );
+
+    assert_eq!(
+        output,
+        "error: Unknown identifier: e.x\n\
+        Traceback, most recent call last:\n    \
+            in <script> at line 7, column 5\n"
+    );
+}
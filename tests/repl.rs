@@ -0,0 +1,100 @@
+//! Drives the REPL through its standard streams, as someone typing into it
+//! would.
+
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+/// Runs a REPL session with the given input, returning what it printed to
+/// its standard output and standard error.
+fn session(input: &str) -> (String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlang"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn definitions_carry_over_between_inputs() {
+    let (stdout, stderr) = session(
+        "let x = 2;\n\
+         fn double(n) {\n\
+             return n * 2;\n\
+         }\n\
+         double(x) + 1\n\
+         print(x);\n",
+    );
+
+    assert_eq!(
+        stdout,
+        "R-Lang REPL, type :help for a list of commands\n\
+         > > ... ... > 5\n\
+         > 2\n\
+         > \n"
+    );
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn errors_do_not_end_the_session() {
+    let (stdout, stderr) = session("missing\nlet = 1;\n1 + 1\n");
+
+    assert!(stdout.ends_with("> > > 2\n> \n"), "{}", stdout);
+    assert_eq!(
+        stderr,
+        "Runtime error: Unknown identifier: missing\n\
+         Traceback, most recent call last:\n    \
+         in <input> at line 1, column 1\n\
+         Parse error: Expected Identifer, got Equals at line 1, column 5\n"
+    );
+}
+
+#[test]
+fn commands() {
+    let (stdout, stderr) = session(
+        "let x = 1;\n\
+         :history\n\
+         :ast\n\
+         :tokens x\n\
+         :unknown\n\
+         :reset\n\
+         x\n\
+         :quit\n\
+         print(1);\n",
+    );
+
+    assert_eq!(
+        stdout,
+        "R-Lang REPL, type :help for a list of commands\n\
+         > > \x20  1  let x = 1;\n   \
+         2  :history\n\
+         > > Identifer \"x\" at line 1, column 1\n\
+         > > Forgot all variables, functions and modules\n\
+         > > "
+    );
+    assert_eq!(
+        stderr,
+        "Usage: :ast CODE\n\
+         Unknown command: :unknown\n\
+         Runtime error: Unknown identifier: x\n\
+         Traceback, most recent call last:\n    \
+         in <input> at line 1, column 1\n"
+    );
+}